@@ -0,0 +1,202 @@
+use crate::error::{Error, Result};
+use crate::main_config::public_instances;
+use crate::prover::TranscriptKind;
+use crate::utils::CIRCUIT_VERSION;
+
+use halo2_proofs::arithmetic::FieldExt;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+
+/// Version of the bundle encodings, bumped whenever a field is added or changes meaning.
+pub const BUNDLE_VERSION: u32 = 2;
+
+/// First bytes of the binary encoding.
+pub const BUNDLE_MAGIC: [u8; 4] = *b"BFPB";
+
+/**
+ * A proof together with everything a separate process needs to verify it, except for the
+ * program itself, which is only bound by its hash, and the verifying key of the circuit.
+ *
+ * The binary encoding is the magic, then every field in declaration order: integers are
+ * little endian, the transcript is its tag byte, byte strings are prefixed by their length
+ * as a u64. The JSON encoding has the same fields, byte strings are hex encoded.
+ */
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofBundle {
+    pub version: u32,
+    pub circuit_version: u32,
+    pub k: u32,
+    pub range: u64,                 // RANGE of the circuit, DOMAIN for the binary
+    pub transcript: TranscriptKind, // Transcript the proof was created with
+    #[serde(with = "hex::serde")]
+    pub program_hash: [u8; 32],
+    #[serde(with = "hex::serde")]
+    pub input: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub output: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub proof: Vec<u8>,
+}
+
+/// Blake2b-256 of the program instance, one 32 bytes little endian word after the other.
+pub fn program_hash<F: FieldExt>(program: &[F]) -> [u8; 32] {
+    let mut state = blake2b_simd::Params::new().hash_length(32).to_state();
+    for word in program {
+        state.update(word.to_repr().as_ref());
+    }
+    state.finalize().as_bytes().try_into().unwrap()
+}
+
+impl ProofBundle {
+    pub fn new<F: FieldExt, const RANGE: usize>(
+        k: u32,
+        transcript: TranscriptKind,
+        program: &[F],
+        input: Vec<u8>,
+        output: Vec<u8>,
+        proof: Vec<u8>,
+    ) -> Self {
+        Self {
+            version: BUNDLE_VERSION,
+            circuit_version: CIRCUIT_VERSION,
+            k,
+            range: RANGE as u64,
+            transcript,
+            program_hash: program_hash(program),
+            input,
+            output,
+            proof,
+        }
+    }
+
+    /// Public instances to verify the proof with. Fails if the bundle was produced by another
+    /// version or configuration of the circuit, or for another program.
+    pub fn instances<F: FieldExt, const RANGE: usize>(
+        &self,
+        program: Vec<F>,
+    ) -> Result<Vec<Vec<F>>> {
+        if self.circuit_version != CIRCUIT_VERSION {
+            return Err(Error::InvalidBundle(format!(
+                "the proof is for circuit version {}, expected {}",
+                self.circuit_version, CIRCUIT_VERSION
+            )));
+        }
+        if self.range != RANGE as u64 {
+            return Err(Error::InvalidBundle(format!(
+                "the proof is for RANGE {}, expected {}",
+                self.range, RANGE
+            )));
+        }
+        if self.program_hash != program_hash(&program) {
+            return Err(Error::InvalidBundle(
+                "the proof is for another program".to_string(),
+            ));
+        }
+        let to_fields =
+            |bytes: &[u8]| -> Vec<F> { bytes.iter().map(|b| F::from(*b as u64)).collect() };
+        Ok(public_instances(
+            program,
+            to_fields(&self.input),
+            to_fields(&self.output),
+        ))
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&BUNDLE_MAGIC)?;
+        writer.write_all(&self.version.to_le_bytes())?;
+        writer.write_all(&self.circuit_version.to_le_bytes())?;
+        writer.write_all(&self.k.to_le_bytes())?;
+        writer.write_all(&self.range.to_le_bytes())?;
+        writer.write_all(&[self.transcript.tag()])?;
+        writer.write_all(&self.program_hash)?;
+        for bytes in [&self.input, &self.output, &self.proof] {
+            writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+            writer.write_all(bytes)?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != BUNDLE_MAGIC {
+            return Err(Error::InvalidBundle("not a proof bundle".to_string()));
+        }
+        let version = read_u32(reader)?;
+        check_version(version)?;
+        let circuit_version = read_u32(reader)?;
+        let k = read_u32(reader)?;
+        let range = read_u64(reader)?;
+        let mut tag = [0u8; 1];
+        reader.read_exact(&mut tag)?;
+        let transcript = TranscriptKind::from_tag(tag[0])
+            .ok_or_else(|| Error::InvalidBundle(format!("unknown transcript tag {}", tag[0])))?;
+        let mut program_hash = [0u8; 32];
+        reader.read_exact(&mut program_hash)?;
+        Ok(Self {
+            version,
+            circuit_version,
+            k,
+            range,
+            transcript,
+            program_hash,
+            input: read_bytes(reader)?,
+            output: read_bytes(reader)?,
+            proof: read_bytes(reader)?,
+        })
+    }
+
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> Result<()> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    pub fn read_json<R: Read>(reader: &mut R) -> Result<Self> {
+        let bundle: Self =
+            serde_json::from_reader(reader).map_err(|err| Error::InvalidBundle(err.to_string()))?;
+        check_version(bundle.version)?;
+        Ok(bundle)
+    }
+
+    /// Decode either encoding, the binary one is recognized by its magic.
+    pub fn from_slice(mut bytes: &[u8]) -> Result<Self> {
+        if bytes.starts_with(&BUNDLE_MAGIC) {
+            Self::read(&mut bytes)
+        } else {
+            Self::read_json(&mut bytes)
+        }
+    }
+}
+
+fn check_version(version: u32) -> Result<()> {
+    if version != BUNDLE_VERSION {
+        return Err(Error::InvalidBundle(format!(
+            "unsupported proof bundle version {}, expected {}",
+            version, BUNDLE_VERSION
+        )));
+    }
+    Ok(())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_u64(reader)?;
+    // The length is not trusted for the allocation, the bytes are read as they come
+    let mut bytes = Vec::new();
+    reader.by_ref().take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,216 @@
+use crate::main_config::MyCircuit;
+use crate::utils::{to_u64, GETCHAR, OPCODES, PUTCHAR};
+use crate::vm;
+
+use ckb_bf_zkvm::matrix::Matrix;
+use halo2_proofs::arithmetic::FieldExt;
+use halo2_proofs::dev::{FailureLocation, VerifyFailure};
+use halo2_proofs::halo2curves::bn256::Fq;
+use std::fmt;
+
+/// Bytes of the source line shown on each side of the offending instruction.
+const SNIPPET_RADIUS: usize = 30;
+
+/// Where an instruction sits in the Brainfuck source, lines and columns start at 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub position: usize, // Byte offset in the source
+    pub line: usize,
+    pub column: usize,
+    pub snippet: String, // Part of the line around the instruction
+    pub caret: usize,    // Offset of the instruction in the snippet
+}
+
+/// A failure reported by the MockProver, mapped back to the trace and the program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub check: String,         // Gate, lookup or copy constraint that failed
+    pub table: Option<String>, // Region of the failure, None outside of regions
+    pub offset: Option<usize>, // Row in the region, or in the circuit outside of regions
+    pub processor_row: Option<usize>,
+    pub cycle: Option<u64>,
+    pub ip: Option<usize>,
+    pub source: Option<SourceLocation>,
+}
+
+/**
+ * Map the failures of `MockProver::verify` for `circuit` to the rows of the trace and to
+ * the instructions of `source`, the program the trace was generated from.
+ *
+ * A failing row of the processor table gives the cycle and ip directly, whichever column
+ * group holds it. Memory rows are traced back to the processor row of the same cycle, input
+ * and output rows to the GETCHAR or PUTCHAR that consumed or printed them, and program rows
+ * only give the ip.
+ */
+pub fn diagnose<F: FieldExt, const RANGE: usize, const GROUPS: usize>(
+    circuit: &MyCircuit<F, RANGE, GROUPS>,
+    source: &[u8],
+    failures: &[VerifyFailure],
+) -> Vec<Diagnostic> {
+    let matrix = circuit.matrix();
+    let height = MyCircuit::<F, RANGE, GROUPS>::usable_rows(circuit.k());
+    let positions = vm::positions(source);
+    failures
+        .iter()
+        .map(|failure| {
+            let (check, location) = match failure {
+                VerifyFailure::CellNotAssigned {
+                    gate,
+                    region,
+                    gate_offset,
+                    ..
+                } => (
+                    format!("{} (cell not assigned)", name(&gate.to_string())),
+                    Some((Some(name(&region.to_string())), *gate_offset)),
+                ),
+                VerifyFailure::ConstraintNotSatisfied {
+                    constraint,
+                    location,
+                    ..
+                } => (gate(&constraint.to_string()), Some(locate(location))),
+                VerifyFailure::ConstraintPoisoned { constraint } => (
+                    format!("{} (poisoned)", gate(&constraint.to_string())),
+                    None,
+                ),
+                VerifyFailure::Lookup { name, location, .. } => {
+                    (name.to_string(), Some(locate(location)))
+                }
+                VerifyFailure::Permutation { column, location } => (
+                    format!("copy constraint on {}", column),
+                    Some(locate(location)),
+                ),
+            };
+            let (table, offset) = match location {
+                Some((table, offset)) => (table, Some(offset)),
+                None => (None, None),
+            };
+            let (processor_row, ip) = match (&table, offset) {
+                (Some(table), Some(offset)) => trace(matrix, table, offset, height),
+                _ => (None, None),
+            };
+            let cycle = processor_row.map(|row| to_u64(matrix.processor_matrix[row].cycle));
+            let source = ip
+                .and_then(|ip| positions.get(ip))
+                .map(|position| SourceLocation::new(source, *position));
+            Diagnostic {
+                check,
+                table,
+                offset,
+                processor_row,
+                cycle,
+                ip,
+                source,
+            }
+        })
+        .collect()
+}
+
+/// The processor row and the ip behind a row of a table, as far as the table tells.
+fn trace(
+    matrix: &Matrix,
+    table: &str,
+    offset: usize,
+    height: usize,
+) -> (Option<usize>, Option<usize>) {
+    let processor_matrix = &matrix.processor_matrix;
+    // Row of the padded trace, see `utils::region_name`
+    let (table, row) = match table
+        .strip_suffix(')')
+        .and_then(|table| table.rsplit_once(" (group "))
+        .and_then(|(table, group)| Some((table, group.parse::<usize>().ok()?)))
+    {
+        Some((table, group)) => (table, group * (height - 1) + offset),
+        None => (table, offset),
+    };
+    // The n-th row of the processor table executing `op`
+    let nth = |op: usize, n: usize| {
+        processor_matrix
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| row.current_instruction == Fq::from(OPCODES[op] as u64))
+            .nth(n)
+            .map(|(idx, _)| idx)
+    };
+    let processor_row = match table {
+        "Load Processor Table" => Some(row).filter(|row| *row < processor_matrix.len()),
+        "Load Memory Table" => matrix
+            .memory_matrix
+            .get(row)
+            .and_then(|m| processor_matrix.iter().position(|row| row.cycle == m.cycle)),
+        // The first row of the input and output tables holds their length
+        "Load Input Table" => offset.checked_sub(1).and_then(|n| nth(GETCHAR, n)),
+        "Load Output Table" => offset.checked_sub(1).and_then(|n| nth(PUTCHAR, n)),
+        "Load Program Table" => return (None, Some(offset)),
+        _ => None,
+    };
+    let ip = processor_row.map(|row| to_u64(processor_matrix[row].instruction_pointer) as usize);
+    (processor_row, ip)
+}
+
+fn locate(location: &FailureLocation) -> (Option<String>, usize) {
+    match location {
+        FailureLocation::InRegion { region, offset } => (Some(name(&region.to_string())), *offset),
+        FailureLocation::OutsideRegion { row } => (None, *row),
+    }
+}
+
+/// The name in `Kind index ('name')`, which is how halo2 displays gates and regions.
+fn name(display: &str) -> String {
+    match (display.find("('"), display.rfind("')")) {
+        (Some(start), Some(end)) if start + 2 <= end => display[start + 2..end].to_string(),
+        _ => display.to_string(),
+    }
+}
+
+/// The gate name of a constraint, displayed as `Constraint index ('name') in gate index ('name')`.
+fn gate(constraint: &str) -> String {
+    match constraint.find(" in gate ") {
+        Some(start) => name(&constraint[start..]),
+        None => constraint.to_string(),
+    }
+}
+
+impl SourceLocation {
+    pub fn new(source: &[u8], position: usize) -> Self {
+        let start = source[..position]
+            .iter()
+            .rposition(|c| *c == b'\n')
+            .map_or(0, |idx| idx + 1);
+        let end = source[position..]
+            .iter()
+            .position(|c| *c == b'\n')
+            .map_or(source.len(), |idx| position + idx);
+        let from = position.saturating_sub(SNIPPET_RADIUS).max(start);
+        let to = (position + SNIPPET_RADIUS + 1).min(end);
+        Self {
+            position,
+            line: source[..position].iter().filter(|c| **c == b'\n').count() + 1,
+            column: position - start + 1,
+            snippet: String::from_utf8_lossy(&source[from..to]).into_owned(),
+            caret: position - from,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.check)?;
+        match (&self.table, self.offset) {
+            (Some(table), Some(offset)) => writeln!(f, "  in {}, row {}", table, offset)?,
+            (None, Some(row)) => writeln!(f, "  at row {} outside of the tables", row)?,
+            _ => {}
+        }
+        if let (Some(row), Some(cycle)) = (self.processor_row, self.cycle) {
+            writeln!(f, "  processor row {}, cycle {}", row, cycle)?;
+        }
+        if let Some(ip) = self.ip {
+            writeln!(f, "  ip {}", ip)?;
+        }
+        if let Some(source) = &self.source {
+            writeln!(f, "  --> line {}, column {}", source.line, source.column)?;
+            writeln!(f, "   | {}", source.snippet)?;
+            writeln!(f, "   | {}^", " ".repeat(source.caret))?;
+        }
+        Ok(())
+    }
+}
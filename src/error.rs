@@ -0,0 +1,116 @@
+use crate::keystore::KeyManifest;
+
+use halo2_proofs::dev::VerifyFailure;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed
+    Io(io::Error),
+    /// The program is not valid Brainfuck, position is the byte offset in the source
+    Compile { position: usize, message: String },
+    /// The program cannot run on the input, e.g. it reads past its end
+    Interpreter(String),
+    /// The execution took more steps than allowed
+    StepLimit { limit: usize },
+    /// The trace needs more rows than a circuit of 2^max_k rows can use
+    CircuitTooLarge { rows: usize, max_k: u32 },
+    /// The trace needs more rows than a circuit of 2^k rows can use, it fits from 2^min_k rows
+    TraceDoesNotFit { rows: usize, k: u32, min_k: u32 },
+    /// Key generation or proving failed
+    Synthesis(halo2_proofs::plonk::Error),
+    /// The proof does not verify against the public instances
+    Verification(halo2_proofs::plonk::Error),
+    /// The MockProver found constraints the trace does not satisfy
+    Unsatisfied(Vec<VerifyFailure>),
+    /// The keys on disk were generated for another circuit
+    KeyMismatch {
+        path: PathBuf,
+        expected: KeyManifest,
+        found: KeyManifest,
+    },
+    /// The proof bundle can't be decoded or is for another circuit or program
+    InvalidBundle(String),
+    /// The segment proofs do not make up one execution of the program
+    InvalidChain(String),
+    /// A proof can't be read by the aggregation circuit, e.g. it has another transcript
+    Aggregation(String),
+    /// The EVM verifier can't be generated, compiled or deployed
+    Evm(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Compile { position, message } => {
+                write!(f, "invalid program at byte {}: {}", position, message)
+            }
+            Self::Interpreter(message) => write!(f, "the interpreter failed: {}", message),
+            Self::StepLimit { limit } => {
+                write!(f, "the execution takes more than {} steps", limit)
+            }
+            Self::CircuitTooLarge { rows, max_k } => write!(
+                f,
+                "the trace needs {} rows, which do not fit into a circuit of 2^{} rows",
+                rows, max_k
+            ),
+            Self::TraceDoesNotFit { rows, k, min_k } => write!(
+                f,
+                "the trace needs {} rows, which do not fit into a circuit of 2^{} rows, use k >= {}",
+                rows, k, min_k
+            ),
+            Self::Synthesis(err) => write!(f, "synthesis failed: {}", err),
+            Self::Verification(err) => write!(f, "the proof is invalid: {}", err),
+            Self::Unsatisfied(failures) => {
+                write!(f, "{} constraints are not satisfied", failures.len())
+            }
+            Self::KeyMismatch {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "the keys in {} are for k = {}, RANGE = {}, circuit version {}, \
+                 expected k = {}, RANGE = {}, circuit version {}",
+                path.display(),
+                found.k,
+                found.range,
+                found.circuit_version,
+                expected.k,
+                expected.range,
+                expected.circuit_version
+            ),
+            Self::InvalidBundle(message) => write!(f, "invalid proof bundle: {}", message),
+            Self::InvalidChain(message) => write!(f, "invalid segment chain: {}", message),
+            Self::Aggregation(message) => write!(f, "aggregation failed: {}", message),
+            Self::Evm(message) => write!(f, "the EVM verifier failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Synthesis(err) | Self::Verification(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Io(err.into())
+    }
+}
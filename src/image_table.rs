@@ -0,0 +1,200 @@
+use crate::utils::*;
+use ckb_bf_zkvm::matrix::Matrix;
+
+use halo2_proofs::arithmetic::FieldExt;
+use halo2_proofs::circuit::{AssignedCell, Layouter, Value};
+use halo2_proofs::plonk::*;
+use halo2_proofs::poly::Rotation;
+use std::collections::BTreeSet;
+
+/**
+ * Memory image table of a segment, one row per cell: mp counts up from 0, start and end are
+ * the values of the cell when the segment starts and ends, copied from the memory instances.
+ * The memory table of the segment looks up the start value of every cell it accesses in here.
+ *
+ * irp accumulates the end values over the start values of the cells that are not accessed,
+ * so that it equals the memory table's erp iff the end image is the start image updated with
+ * the last value of every accessed cell. A wrong accessed flag leaves a cell without its end
+ * value or with two of them, so the flags are bound as well.
+ */
+#[derive(Clone, Debug, Copy)]
+pub struct ImageTableConfig {
+    pub(crate) mp: Column<Advice>,
+    pub(crate) start: Column<Advice>,
+    end: Column<Advice>,
+    memory_in: Column<Instance>, // The memory when the segment starts, one cell per row
+    memory_out: Column<Instance>, // The memory when the segment ends, one cell per row
+    accessed: Column<Advice>,    // 1 if the memory table accesses the cell
+    irp: Column<Advice>, // Running product of (mp, end) over (mp, start) of the cells left alone
+    pub(crate) s_lookup: Selector, // Selector for every row, table side of the lookup
+    s_b: Selector,       // Selector for the first row
+    s_i: Selector,       // Selector for the rows after the first one
+    challenges: Challenges,
+}
+
+impl<F: FieldExt> Config<F> for ImageTableConfig {
+    fn configure(cs: &mut ConstraintSystem<F>, challenges: Challenges) -> Self {
+        let one = Expression::Constant(F::one());
+
+        let mp = cs.advice_column();
+        let start = cs.advice_column();
+        let end = cs.advice_column();
+        let memory_in = cs.instance_column();
+        let memory_out = cs.instance_column();
+        for column in [start, end] {
+            cs.enable_equality(column);
+        }
+        for column in [memory_in, memory_out] {
+            cs.enable_equality(column);
+        }
+        let accessed = cs.advice_column();
+        let irp = cs.advice_column_in(SecondPhase);
+        cs.enable_equality(irp);
+        let s_lookup = cs.complex_selector();
+        let s_b = cs.selector();
+        let s_i = cs.selector();
+
+        cs.create_gate("IM0: mp_0 = 0", |vc| {
+            let s_b = vc.query_selector(s_b);
+            let mp = vc.query_advice(mp, Rotation::cur());
+            vec![s_b * mp]
+        });
+
+        cs.create_gate("IM1: mp increases by one per row", |vc| {
+            let s_i = vc.query_selector(s_i);
+            let prev_mp = vc.query_advice(mp, Rotation::prev());
+            let cur_mp = vc.query_advice(mp, Rotation::cur());
+            vec![s_i * (cur_mp - prev_mp - one.clone())]
+        });
+
+        cs.create_gate("IM2: accessed is 0 or 1", |vc| {
+            let s_lookup = vc.query_selector(s_lookup);
+            let accessed = vc.query_advice(accessed, Rotation::cur());
+            vec![s_lookup * accessed.clone() * (one.clone() - accessed)]
+        });
+
+        // irp_i * (beta - (mp_i + gamma * end_i)) = irp_{i-1} * start factor of row i
+        let factors = |vc: &mut VirtualCells<'_, F>| {
+            let beta = vc.query_challenge(challenges.beta);
+            let gamma = vc.query_challenge(challenges.gamma);
+            let mp = vc.query_advice(mp, Rotation::cur());
+            let start = vc.query_advice(start, Rotation::cur());
+            let end = vc.query_advice(end, Rotation::cur());
+            let accessed = vc.query_advice(accessed, Rotation::cur());
+            let end_factor = beta.clone() - (mp.clone() + gamma.clone() * end);
+            let start_factor =
+                (one.clone() - accessed.clone()) * (beta - (mp + gamma * start)) + accessed;
+            (end_factor, start_factor)
+        };
+
+        cs.create_gate(
+            "IM3: irp_0 * (beta - (mp_0 + gamma * end_0)) = beta - (mp_0 + gamma * start_0) if not accessed",
+            |vc| {
+                let s_b = vc.query_selector(s_b);
+                let irp = vc.query_advice(irp, Rotation::cur());
+                let (end_factor, start_factor) = factors(vc);
+                vec![s_b * (irp * end_factor - start_factor)]
+            },
+        );
+
+        cs.create_gate(
+            "IM4: irp_i * (beta - (mp_i + gamma * end_i)) = irp_{i-1} * (beta - (mp_i + gamma * start_i)) if not accessed",
+            |vc| {
+                let s_i = vc.query_selector(s_i);
+                let prev_irp = vc.query_advice(irp, Rotation::prev());
+                let cur_irp = vc.query_advice(irp, Rotation::cur());
+                let (end_factor, start_factor) = factors(vc);
+                vec![s_i * (cur_irp * end_factor - prev_irp * start_factor)]
+            },
+        );
+
+        Self {
+            mp,
+            start,
+            end,
+            memory_in,
+            memory_out,
+            accessed,
+            irp,
+            s_lookup,
+            s_b,
+            s_i,
+            challenges,
+        }
+    }
+
+    fn load_table(&self, layouter: &mut impl Layouter<F>, matrix: &Matrix) -> Result<(), Error> {
+        self.assign_table(layouter, matrix, height(matrix, 1))
+            .map(|_| ())
+    }
+}
+
+impl ImageTableConfig {
+    /// Load the image table and return the last cell of irp, which the segment config
+    /// constrains to be equal to the memory table's erp. The table spans the first `len`
+    /// rows, so the segment only accesses cells below `len`.
+    pub fn assign_table<F: FieldExt>(
+        &self,
+        layouter: &mut impl Layouter<F>,
+        matrix: &Matrix,
+        len: usize,
+    ) -> Result<AssignedCell<F, F>, Error> {
+        let beta = layouter.get_challenge(self.challenges.beta);
+        let gamma = layouter.get_challenge(self.challenges.gamma);
+        let accessed = matrix
+            .memory_matrix
+            .iter()
+            .map(|row| to_u64(row.memory_pointer))
+            .collect::<BTreeSet<_>>();
+        layouter.assign_region(
+            || "Load Image Table",
+            |mut region| {
+                let mut irp = Value::known(F::one());
+                let mut irp_cell = None;
+                self.s_b.enable(&mut region, 0)?;
+                for offset in 0..len {
+                    if offset > 0 {
+                        self.s_i.enable(&mut region, offset)?;
+                    }
+                    self.s_lookup.enable(&mut region, offset)?;
+                    let mp = F::from(offset as u64);
+                    region.assign_advice(|| "mp", self.mp, offset, || Value::known(mp))?;
+                    // Past the memory the instances are zero
+                    let start = region.assign_advice_from_instance(
+                        || "start",
+                        self.memory_in,
+                        offset,
+                        self.start,
+                        offset,
+                    )?;
+                    let end = region.assign_advice_from_instance(
+                        || "end",
+                        self.memory_out,
+                        offset,
+                        self.end,
+                        offset,
+                    )?;
+                    let accessed = accessed.contains(&(offset as u64));
+                    region.assign_advice(
+                        || "accessed",
+                        self.accessed,
+                        offset,
+                        || Value::known(F::from(accessed as u64)),
+                    )?;
+                    let mp = Value::known(mp);
+                    let start_factor = if accessed {
+                        Value::known(F::one())
+                    } else {
+                        beta - (mp + gamma * start.value().copied())
+                    };
+                    let end_factor = beta - (mp + gamma * end.value().copied());
+                    irp = irp
+                        * start_factor
+                        * end_factor.map(|factor| factor.invert().unwrap_or(F::zero()));
+                    irp_cell = Some(region.assign_advice(|| "irp", self.irp, offset, || irp)?);
+                }
+                irp_cell.ok_or(Error::Synthesis)
+            },
+        )
+    }
+}
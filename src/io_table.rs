@@ -0,0 +1,206 @@
+use crate::utils::*;
+use ckb_bf_zkvm::matrix::Matrix;
+
+use halo2_proofs::arithmetic::FieldExt;
+use halo2_proofs::circuit::{AssignedCell, Layouter, Value};
+use halo2_proofs::halo2curves::bn256::Fq;
+use halo2_proofs::plonk::*;
+use halo2_proofs::poly::Rotation;
+
+/// Input table, the bytes consumed by GETCHAR, evaluated at delta.
+pub type InputTableConfig = IoTableConfig<GETCHAR>;
+
+/// Output table, the bytes printed by PUTCHAR, evaluated at epsilon.
+pub type OutputTableConfig = IoTableConfig<PUTCHAR>;
+
+/**
+ * I/O table of the bytes read or written by the instruction `OP`, GETCHAR or PUTCHAR.
+ * The first row is a header that holds the number of bytes and starts the running evaluation,
+ * the following rows hold the bytes in order. Both are copied from the instance, row for row.
+ * The table spans every row, rem counts down the bytes left and only rows where it decreases
+ * are accumulated into the running evaluation.
+ */
+#[derive(Clone, Debug, Copy)]
+pub struct IoTableConfig<const OP: usize> {
+    value: Column<Advice>,
+    instance: Column<Instance>, // The number of bytes, then the bytes one per row
+    re: Column<Advice>,         // Running evaluation of value, links to the processor table
+    rem: Column<Advice>,        // Number of bytes after this row
+    s_b: Selector,              // Selector for the header row
+    s_v: Selector,              // Selector for rows that may hold a value
+    s_n: Selector,              // Selector for rows that have a next row holding a value
+    s_last: Selector,           // Selector for the last row
+    challenges: Challenges,
+}
+
+impl<const OP: usize> IoTableConfig<OP> {
+    /// Names of the gates IO0-IO3, prefixed by the table.
+    fn gate_names() -> [&'static str; 4] {
+        match OP {
+            GETCHAR => [
+                "IN0: re_0 = 1 and rem_0 = value_0",
+                "IN1: rem decreases by 0 or 1, if it decreases re_i = re_{i-1} * delta + value_i",
+                "IN2: once rem stays the same, it never decreases again",
+                "IN3: rem_last = 0",
+            ],
+            PUTCHAR => [
+                "OUT0: re_0 = 1 and rem_0 = value_0",
+                "OUT1: rem decreases by 0 or 1, if it decreases re_i = re_{i-1} * epsilon + value_i",
+                "OUT2: once rem stays the same, it never decreases again",
+                "OUT3: rem_last = 0",
+            ],
+            _ => unreachable!("only GETCHAR and PUTCHAR have an I/O table"),
+        }
+    }
+
+    /// Evaluation point of the running evaluation, the processor table uses the same one.
+    fn challenge(challenges: Challenges) -> Challenge {
+        match OP {
+            GETCHAR => challenges.delta,
+            _ => challenges.epsilon,
+        }
+    }
+
+    fn values(matrix: &Matrix) -> &[Fq] {
+        match OP {
+            GETCHAR => &matrix.input_matrix,
+            _ => &matrix.output_matrix,
+        }
+    }
+}
+
+impl<F: FieldExt, const OP: usize> Config<F> for IoTableConfig<OP> {
+    fn configure(cs: &mut ConstraintSystem<F>, challenges: Challenges) -> Self {
+        let one = Expression::Constant(F::one());
+        let [io0, io1, io2, io3] = Self::gate_names();
+
+        let value = cs.advice_column();
+        let instance = cs.instance_column();
+        cs.enable_equality(value);
+        cs.enable_equality(instance);
+        let re = cs.advice_column_in(SecondPhase);
+        cs.enable_equality(re);
+        let rem = cs.advice_column();
+        let s_b = cs.selector();
+        let s_v = cs.selector();
+        let s_n = cs.selector();
+        let s_last = cs.selector();
+
+        cs.create_gate(io0, |vc| {
+            let s_b = vc.query_selector(s_b);
+            let re = vc.query_advice(re, Rotation::cur());
+            let rem = vc.query_advice(rem, Rotation::cur());
+            let value = vc.query_advice(value, Rotation::cur());
+            vec![s_b.clone() * (re - one.clone()), s_b * (rem - value)]
+        });
+
+        cs.create_gate(io1, |vc| {
+            let s_v = vc.query_selector(s_v);
+            let point = vc.query_challenge(Self::challenge(challenges));
+            let prev_rem = vc.query_advice(rem, Rotation::prev());
+            let cur_rem = vc.query_advice(rem, Rotation::cur());
+            let prev_re = vc.query_advice(re, Rotation::prev());
+            let cur_re = vc.query_advice(re, Rotation::cur());
+            let value = vc.query_advice(value, Rotation::cur());
+            let active = prev_rem - cur_rem;
+            vec![
+                s_v.clone() * active.clone() * (one.clone() - active.clone()),
+                s_v * (cur_re
+                    - prev_re.clone()
+                    - active * (prev_re * (point - one.clone()) + value)),
+            ]
+        });
+
+        // Otherwise the prover could skip bytes of the instance
+        cs.create_gate(io2, |vc| {
+            let s_n = vc.query_selector(s_n);
+            let prev_rem = vc.query_advice(rem, Rotation::prev());
+            let cur_rem = vc.query_advice(rem, Rotation::cur());
+            let next_rem = vc.query_advice(rem, Rotation::next());
+            vec![s_n * (one.clone() - (prev_rem - cur_rem.clone())) * (cur_rem - next_rem)]
+        });
+
+        cs.create_gate(io3, |vc| {
+            let s_last = vc.query_selector(s_last);
+            let rem = vc.query_advice(rem, Rotation::cur());
+            vec![s_last * rem]
+        });
+
+        Self {
+            value,
+            instance,
+            re,
+            rem,
+            s_b,
+            s_v,
+            s_n,
+            s_last,
+            challenges,
+        }
+    }
+
+    fn load_table(&self, layouter: &mut impl Layouter<F>, matrix: &Matrix) -> Result<(), Error> {
+        self.assign_table(layouter, matrix, height(matrix, 1))
+            .map(|_| ())
+    }
+}
+
+impl<const OP: usize> IoTableConfig<OP> {
+    /// Load the table and return the last cell of the running evaluation,
+    /// which the main config constrains to be equal to the processor table's one.
+    /// The table spans the first `len` rows.
+    pub fn assign_table<F: FieldExt>(
+        &self,
+        layouter: &mut impl Layouter<F>,
+        matrix: &Matrix,
+        len: usize,
+    ) -> Result<AssignedCell<F, F>, Error> {
+        let point = layouter.get_challenge(Self::challenge(self.challenges));
+        let name = match OP {
+            GETCHAR => "Load Input Table",
+            _ => "Load Output Table",
+        };
+        layouter.assign_region(
+            || name,
+            |mut region| {
+                let values = Self::values(matrix);
+                let mut re = Value::known(F::one());
+                self.s_b.enable(&mut region, 0)?;
+                self.s_last.enable(&mut region, len - 1)?;
+                region.assign_advice_from_instance(|| "value", self.instance, 0, self.value, 0)?;
+                region.assign_advice(
+                    || "rem",
+                    self.rem,
+                    0,
+                    || Value::known(F::from(values.len() as u64)),
+                )?;
+                let mut re_cell = region.assign_advice(|| "re", self.re, 0, || re)?;
+                for offset in 1..len {
+                    self.s_v.enable(&mut region, offset)?;
+                    if offset < len - 1 {
+                        self.s_n.enable(&mut region, offset)?;
+                    }
+                    // Past the bytes the instance is zero
+                    region.assign_advice_from_instance(
+                        || "value",
+                        self.instance,
+                        offset,
+                        self.value,
+                        offset,
+                    )?;
+                    if let Some(value) = values.get(offset - 1) {
+                        re = re * point + Value::known(to_field(*value));
+                    }
+                    region.assign_advice(
+                        || "rem",
+                        self.rem,
+                        offset,
+                        || Value::known(F::from(values.len().saturating_sub(offset) as u64)),
+                    )?;
+                    re_cell = region.assign_advice(|| "re", self.re, offset, || re)?;
+                }
+                Ok(re_cell)
+            },
+        )
+    }
+}
@@ -0,0 +1,163 @@
+use crate::error::{Error, Result};
+use crate::main_config::MyCircuit;
+use crate::prover;
+use crate::utils::CIRCUIT_VERSION;
+use crate::vm;
+
+use halo2_proofs::halo2curves::bn256::{Bn256, Fr, G1Affine};
+use halo2_proofs::plonk::{ProvingKey, VerifyingKey};
+use halo2_proofs::poly::commitment::Params;
+use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+use halo2_proofs::SerdeFormat;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// What the keys in a directory of the store were generated for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyManifest {
+    pub k: u32,
+    pub range: u64,
+    pub circuit_version: u32,
+}
+
+/**
+ * Directory of SRS params and keys of `MyCircuit`, one sub-directory per
+ * (k, RANGE, circuit version) holding `manifest.json`, `params.bin`, `vk.bin` and `pk.bin`.
+ * A verifier only needs the first three.
+ */
+pub struct KeyStore {
+    dir: PathBuf,
+}
+
+impl KeyStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn manifest<const RANGE: usize>(k: u32) -> KeyManifest {
+        KeyManifest {
+            k,
+            range: RANGE as u64,
+            circuit_version: CIRCUIT_VERSION,
+        }
+    }
+
+    /// Sub-directory of the keys for circuits of size 2^k.
+    pub fn path<const RANGE: usize>(&self, k: u32) -> PathBuf {
+        self.dir
+            .join(format!("k{}_range{}_v{}", k, RANGE, CIRCUIT_VERSION))
+    }
+
+    pub fn contains<const RANGE: usize>(&self, k: u32) -> bool {
+        self.path::<RANGE>(k).join("manifest.json").exists()
+    }
+
+    pub fn save<const RANGE: usize>(
+        &self,
+        params: &ParamsKZG<Bn256>,
+        pk: &ProvingKey<G1Affine>,
+    ) -> Result<()> {
+        let path = self.path::<RANGE>(params.k());
+        std::fs::create_dir_all(&path)?;
+        let mut writer = BufWriter::new(File::create(path.join("params.bin"))?);
+        params.write(&mut writer)?;
+        writer.flush()?;
+        let mut writer = BufWriter::new(File::create(path.join("vk.bin"))?);
+        pk.get_vk().write(&mut writer)?;
+        writer.flush()?;
+        let mut writer = BufWriter::new(File::create(path.join("pk.bin"))?);
+        pk.write(&mut writer, SerdeFormat::RawBytes)?;
+        writer.flush()?;
+        // Written last, a directory without it is incomplete
+        let writer = File::create(path.join("manifest.json"))?;
+        serde_json::to_writer_pretty(writer, &Self::manifest::<RANGE>(params.k()))?;
+        Ok(())
+    }
+
+    /// Load the SRS params and the verifying key for circuits of size 2^k.
+    pub fn load_verifier<const RANGE: usize>(
+        &self,
+        k: u32,
+    ) -> Result<(ParamsKZG<Bn256>, VerifyingKey<G1Affine>)> {
+        let path = self.path::<RANGE>(k);
+        let expected = Self::manifest::<RANGE>(k);
+        let found: KeyManifest = serde_json::from_reader(open(&path.join("manifest.json"))?)?;
+        let params = ParamsKZG::<Bn256>::read(&mut open(&path.join("params.bin"))?)?;
+        let params_manifest = KeyManifest {
+            k: params.k(),
+            ..found
+        };
+        for found in [found, params_manifest] {
+            if found != expected {
+                return Err(Error::KeyMismatch {
+                    path,
+                    expected,
+                    found,
+                });
+            }
+        }
+        let vk = VerifyingKey::<G1Affine>::read::<_, MyCircuit<Fr, RANGE>>(
+            &mut open(&path.join("vk.bin"))?,
+            &params,
+        )?;
+        Ok((params, vk))
+    }
+
+    /// Load the SRS params and the proving key for circuits of size 2^k. The directory is
+    /// checked like `load_verifier` does, and the proving key must hold the stored verifying key.
+    pub fn load_prover<const RANGE: usize>(
+        &self,
+        k: u32,
+    ) -> Result<(ParamsKZG<Bn256>, ProvingKey<G1Affine>)> {
+        let (params, vk) = self.load_verifier::<RANGE>(k)?;
+        let path = self.path::<RANGE>(k).join("pk.bin");
+        let pk = ProvingKey::<G1Affine>::read::<_, MyCircuit<Fr, RANGE>>(
+            &mut open(&path)?,
+            SerdeFormat::RawBytes,
+        )?;
+        let (mut stored, mut found) = (Vec::new(), Vec::new());
+        vk.write(&mut stored)?;
+        pk.get_vk().write(&mut found)?;
+        if stored != found {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} does not hold the stored verifying key", path.display()),
+            )));
+        }
+        Ok((params, pk))
+    }
+
+    /// Generate the keys for circuits of size 2^k and save them. The keys do not depend on the
+    /// trace, so the circuit of the empty program provides the shape.
+    pub fn generate<const RANGE: usize>(
+        &self,
+        k: u32,
+    ) -> Result<(ParamsKZG<Bn256>, ProvingKey<G1Affine>)> {
+        let circuit = MyCircuit::<Fr, RANGE>::new(vm::run(b"", &[], None)?, k)?;
+        let params = prover::setup(k);
+        let pk = prover::keygen(&params, &circuit)?;
+        self.save::<RANGE>(&params, &pk)?;
+        Ok((params, pk))
+    }
+
+    /// Load the keys for circuits of the size of `circuit`, generating and saving them if missing.
+    pub fn load_or_generate<const RANGE: usize>(
+        &self,
+        circuit: &MyCircuit<Fr, RANGE>,
+    ) -> Result<(ParamsKZG<Bn256>, ProvingKey<G1Affine>)> {
+        let k = circuit.k();
+        if self.contains::<RANGE>(k) {
+            return self.load_prover::<RANGE>(k);
+        }
+        let params = prover::setup(k);
+        let pk = prover::keygen(&params, circuit)?;
+        self.save::<RANGE>(&params, &pk)?;
+        Ok((params, pk))
+    }
+}
+
+fn open(path: &Path) -> io::Result<BufReader<File>> {
+    File::open(path).map(BufReader::new)
+}
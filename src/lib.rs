@@ -1,6 +1,20 @@
 pub mod processor_table;
 pub mod memory_table;
-pub mod instruction_table;
+pub mod bundle;
+pub mod diagnostics;
+pub mod error;
+pub mod image_table;
+pub mod io_table;
+pub mod keystore;
+pub mod opcode_table;
+pub mod padding;
+pub mod program_table;
+pub mod prover;
 pub mod range_table;
+pub mod rle;
+pub mod segment;
 pub mod utils;
 pub mod main_config;
+pub mod vm;
+
+pub use error::{Error, Result};
@@ -1,32 +1,220 @@
-use ckb_bf_zkvm::code;
-use ckb_bf_zkvm::interpreter::Interpreter;
+use ckb_bf_zkvm::matrix::Matrix;
+use clap::{Args, Parser, Subcommand};
+use halo2_bf::bundle::ProofBundle;
+use halo2_bf::keystore::KeyStore;
 use halo2_bf::main_config::MyCircuit;
-use halo2_bf::utils::DOMAIN;
-use halo2_proofs::dev::MockProver;
-use halo2_proofs::halo2curves::bn256::Fq;
-use std::io::Read;
-
-/**
- * halo2_bf <path_to_program> 
- */
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = std::env::args().collect();
-    assert!(args.len() >= 2);
-    let mut f = std::fs::File::open(&args[1])?;
-    let mut c: Vec<u8> = Vec::new();
-    f.read_to_end(&mut c)?;
-    let mut i = Interpreter::new();
-    i.set_code(code::compile(c));
-    i.run();
-    let k = i
-        .matrix
-        .instruction_matrix
-        .len()
-        .next_power_of_two()
-        .trailing_zeros();
-
-    let circuit = MyCircuit::<Fq, { DOMAIN }>::new(i.matrix);
-    let prover = MockProver::run(k, &circuit, vec![]).unwrap();
-    prover.assert_satisfied();
+use halo2_bf::prover::TranscriptKind;
+use halo2_bf::utils::{to_bytes, DOMAIN};
+use halo2_bf::{diagnostics, prover, vm, Error, Result};
+use halo2_proofs::halo2curves::bn256::Fr;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+type Circuit = MyCircuit<Fr, { DOMAIN }>;
+
+#[derive(Parser)]
+#[command(
+    name = "halo2_bf",
+    about = "Prove the execution of Brainfuck programs with halo2"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Interpret the program and print its output
+    Run {
+        #[command(flatten)]
+        program: ProgramArgs,
+    },
+    /// Check the execution with the MockProver and report unsatisfied constraints
+    Mock {
+        #[command(flatten)]
+        program: ProgramArgs,
+        /// Circuit size 2^k, the smallest one that fits the trace by default
+        #[arg(long)]
+        k: Option<u32>,
+    },
+    /// Generate the keys for circuits of size 2^k and save them in the key store.
+    /// The keys only depend on k, they serve every program that fits, see `mock` for the k
+    /// of a program.
+    Keygen {
+        /// Circuit size 2^k
+        #[arg(long)]
+        k: u32,
+        #[command(flatten)]
+        keys: KeyArgs,
+    },
+    /// Prove the execution and write the proof bundle, which holds the input and output
+    Prove {
+        #[command(flatten)]
+        program: ProgramArgs,
+        /// Circuit size 2^k, the smallest one that fits the trace by default
+        #[arg(long)]
+        k: Option<u32>,
+        #[command(flatten)]
+        keys: KeyArgs,
+        #[arg(long, default_value = "proof.bin")]
+        proof: PathBuf,
+        /// Write the proof bundle as JSON instead of binary
+        #[arg(long)]
+        json: bool,
+        /// Hash of the Fiat-Shamir transcript: blake2b, keccak256 or poseidon
+        #[arg(long, default_value = "blake2b")]
+        transcript: TranscriptKind,
+    },
+    /// Verify a proof bundle against the program and print the output it proves
+    Verify {
+        /// Path to the Brainfuck program
+        program: PathBuf,
+        #[command(flatten)]
+        keys: KeyArgs,
+        #[arg(long, default_value = "proof.bin")]
+        proof: PathBuf,
+    },
+}
+
+#[derive(Args)]
+struct ProgramArgs {
+    /// Path to the Brainfuck program
+    program: PathBuf,
+    /// Bytes read by GETCHAR
+    #[arg(long, conflicts_with = "input_file")]
+    input: Option<String>,
+    /// File holding the bytes read by GETCHAR
+    #[arg(long)]
+    input_file: Option<PathBuf>,
+    /// Stop with an error if the execution takes more steps
+    #[arg(long)]
+    max_steps: Option<usize>,
+}
+
+#[derive(Args)]
+struct KeyArgs {
+    /// Directory of the key store
+    #[arg(long, default_value = "keys")]
+    keys: PathBuf,
+}
+
+impl ProgramArgs {
+    fn code(&self) -> std::io::Result<Vec<u8>> {
+        std::fs::read(&self.program)
+    }
+
+    fn input(&self) -> std::io::Result<Vec<u8>> {
+        read_bytes(&self.input, &self.input_file)
+    }
+
+    fn run(&self) -> Result<Matrix> {
+        vm::run(&self.code()?, &self.input()?, self.max_steps)
+    }
+}
+
+impl KeyArgs {
+    fn store(&self) -> KeyStore {
+        KeyStore::new(&self.keys)
+    }
+}
+
+fn read_bytes(value: &Option<String>, file: &Option<PathBuf>) -> std::io::Result<Vec<u8>> {
+    match (value, file) {
+        (Some(value), _) => Ok(value.as_bytes().to_vec()),
+        (_, Some(file)) => std::fs::read(file),
+        (None, None) => Ok(Vec::new()),
+    }
+}
+
+fn circuit(matrix: Matrix, k: Option<u32>) -> Result<Circuit> {
+    let k = match k {
+        Some(k) => k,
+        None => Circuit::min_k(&matrix)?,
+    };
+    Circuit::new(matrix, k)
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    std::fs::write(path, bytes)?;
+    eprintln!("Wrote {}", path.display());
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = run(Cli::parse()) {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    match cli.command {
+        Command::Run { program } => {
+            let matrix = program.run()?;
+            std::io::stdout().write_all(&to_bytes(&matrix.output_matrix))?;
+        }
+        Command::Mock { program, k } => {
+            let source = program.code()?;
+            let circuit = circuit(program.run()?, k)?;
+            let result = prover::mock(&circuit);
+            if let Err(Error::Unsatisfied(failures)) = &result {
+                for diagnostic in diagnostics::diagnose(&circuit, &source, failures) {
+                    eprintln!("{}", diagnostic);
+                }
+            }
+            result?;
+            eprintln!("All constraints are satisfied (k = {})", circuit.k());
+        }
+        Command::Keygen { k, keys } => {
+            let store = keys.store();
+            store.generate::<{ DOMAIN }>(k)?;
+            eprintln!("Wrote {}", store.path::<{ DOMAIN }>(k).display());
+        }
+        Command::Prove {
+            program,
+            k,
+            keys,
+            proof,
+            json,
+            transcript,
+        } => {
+            let matrix = program.run()?;
+            // Only the bytes consumed by GETCHAR are part of the public input
+            let input = to_bytes(&matrix.input_matrix);
+            let output = to_bytes(&matrix.output_matrix);
+            let circuit = circuit(matrix, k)?;
+            let k = circuit.k();
+            let words = circuit.instances()[0].clone();
+            let (params, pk) = keys.store().load_prover::<{ DOMAIN }>(k)?;
+            let bundle = ProofBundle::new::<_, { DOMAIN }>(
+                k,
+                transcript,
+                &words,
+                input,
+                output,
+                prover::prove_with(&params, &pk, circuit, transcript)?,
+            );
+            let mut bytes = Vec::new();
+            if json {
+                bundle.write_json(&mut bytes)?;
+            } else {
+                bundle.write(&mut bytes)?;
+            }
+            write_file(&proof, &bytes)?;
+        }
+        Command::Verify {
+            program,
+            keys,
+            proof,
+        } => {
+            let bundle = ProofBundle::from_slice(&std::fs::read(&proof)?)?;
+            let (params, vk) = keys.store().load_verifier::<{ DOMAIN }>(bundle.k)?;
+            let words = vm::compile::<Fr>(&std::fs::read(&program)?)?;
+            let instances = bundle.instances::<_, { DOMAIN }>(words)?;
+            prover::verify_with(&params, &vk, &bundle.proof, &instances, bundle.transcript)?;
+            eprintln!("The proof is valid, the program printed:");
+            std::io::stdout().write_all(&bundle.output)?;
+        }
+    }
     Ok(())
 }
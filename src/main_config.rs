@@ -1,79 +1,359 @@
-use crate::instruction_table::InstructionTableConfig;
+use crate::error;
+use crate::io_table::{InputTableConfig, OutputTableConfig};
 use crate::memory_table::MemoryTableConfig;
-use crate::processor_table::ProcessorTableConfig;
+use crate::opcode_table::OpcodeTableConfig;
+use crate::padding::pad;
+use crate::processor_table::{ProcessorTableConfig, ProcessorTerminals};
+use crate::program_table::ProgramTableConfig;
+use crate::range_table::RangeTableConfig;
 use crate::utils::*;
 use ckb_bf_zkvm::matrix::Matrix;
 
-use halo2_proofs::arithmetic::Field;
-use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::arithmetic::FieldExt;
+use halo2_proofs::circuit::{AssignedCell, Layouter, SimpleFloorPlanner};
 use halo2_proofs::halo2curves::bn256::Fq;
 use halo2_proofs::plonk::*;
+use halo2_proofs::poly::Rotation;
 use std::marker::PhantomData;
+
 /**
- * TODO: What's Misssing?
- * 1. permutation running product (prp) to link processor table and memory table
- * 2. running evaluation (re) to link processor table and output table
- * 3. re to link processor table and input table
- * 4. link processor table and instruction table
- * 4. read public input and expose public output 
+ * The processor and memory tables grow with the trace, so they can be folded into `GROUPS`
+ * column groups, each spanning every row. Group `i + 1` starts with a copy of the last row of
+ * group `i`, so the transitions between groups are checked by the same gates as within a group.
+ * A trace of n rows then needs (n - 1) / GROUPS + 1 rows, which trades columns for a smaller k.
  */
-
 #[derive(Clone, Debug, Copy)]
-pub struct MainConfig<const RANGE: usize> {
-    p_config: ProcessorTableConfig<RANGE>,
-    m_config: MemoryTableConfig,
-    i_config: InstructionTableConfig,
+pub struct MainConfig<const RANGE: usize, const GROUPS: usize> {
+    p_configs: [ProcessorTableConfig<RANGE>; GROUPS],
+    m_configs: [MemoryTableConfig; GROUPS],
+    program_config: ProgramTableConfig,
+    input_config: InputTableConfig,
+    output_config: OutputTableConfig,
 }
 
-impl<const RANGE: usize> Config for MainConfig<RANGE> {
-    fn configure(cs: &mut ConstraintSystem<Fq>) -> Self {
+impl<F: FieldExt, const RANGE: usize, const GROUPS: usize> Config<F> for MainConfig<RANGE, GROUPS> {
+    fn configure(cs: &mut ConstraintSystem<F>, challenges: Challenges) -> Self {
+        assert!(GROUPS > 0, "the trace needs at least one column group");
+        let lookup_table = RangeTableConfig::<RANGE>::configure(cs, challenges);
+        let opcode_table = OpcodeTableConfig::configure(cs, challenges);
+        let p_configs = [(); GROUPS].map(|_| {
+            ProcessorTableConfig::configure_group(cs, challenges, lookup_table, opcode_table, true)
+        });
+        let m_configs = [(); GROUPS].map(|_| MemoryTableConfig::configure(cs, challenges));
+        let program_config = ProgramTableConfig::configure(cs, challenges);
+        let input_config = InputTableConfig::configure(cs, challenges);
+        let output_config = OutputTableConfig::configure(cs, challenges);
+
+        for m_config in m_configs {
+            lookup_memory(cs, m_config, lookup_table);
+        }
+        for p_config in p_configs {
+            lookup_program(cs, p_config, program_config);
+        }
+
         Self {
-            p_config: ProcessorTableConfig::configure(cs),
-            m_config: MemoryTableConfig::configure(cs),
-            i_config: InstructionTableConfig::configure(cs),
+            p_configs,
+            m_configs,
+            program_config,
+            input_config,
+            output_config,
+        }
+    }
+
+    fn load_table(&self, layouter: &mut impl Layouter<F>, matrix: &Matrix) -> Result<(), Error> {
+        let height = height(matrix, GROUPS);
+        let mut p_groups = Vec::with_capacity(GROUPS);
+        for (group, p_config) in self.p_configs.iter().enumerate() {
+            // The trace ends with a halting row in the last group
+            let halt = group == GROUPS - 1;
+            p_groups.push(p_config.assign_group(
+                layouter,
+                matrix,
+                group,
+                height,
+                F::zero(),
+                halt,
+            )?);
+        }
+        let mut m_groups = Vec::with_capacity(GROUPS);
+        for (group, m_config) in self.m_configs.iter().enumerate() {
+            m_groups.push(m_config.assign_group(layouter, matrix, group, height)?);
         }
+        let input_re = self.input_config.assign_table(layouter, matrix, height)?;
+        let output_re = self.output_config.assign_table(layouter, matrix, height)?;
+        // Each group carries on from the last row of the previous one
+        layouter.assign_region(
+            || "Link column groups",
+            |mut region| {
+                for pair in p_groups.windows(2) {
+                    pair[0].0.link(&mut region, &pair[1].0)?;
+                }
+                for pair in m_groups.windows(2) {
+                    pair[0].0.link(&mut region, &pair[1].0)?;
+                }
+                Ok(())
+            },
+        )?;
+        let p_terminals = &p_groups[GROUPS - 1].1;
+        let m_prp = &m_groups[GROUPS - 1].1.prp;
+        constrain_terminals(layouter, p_terminals, m_prp, &input_re, &output_re)?;
+        self.program_config.assign_table(layouter, matrix, height)
     }
+}
+
+/// Constrain the running products and evaluations of the last processor row to those of
+/// the memory, input and output tables.
+pub(crate) fn constrain_terminals<F: FieldExt>(
+    layouter: &mut impl Layouter<F>,
+    p_terminals: &ProcessorTerminals<F>,
+    m_prp: &AssignedCell<F, F>,
+    input_re: &AssignedCell<F, F>,
+    output_re: &AssignedCell<F, F>,
+) -> Result<(), Error> {
+    // The memory table is a permutation of the processor table
+    // iff both running products end up with the same value
+    layouter.assign_region(
+        || "PRP: processor table and memory table",
+        |mut region| region.constrain_equal(p_terminals.prp.cell(), m_prp.cell()),
+    )?;
+    // GETCHAR consumes exactly the input table
+    // iff both running evaluations end up with the same value
+    layouter.assign_region(
+        || "RE: processor table and input table",
+        |mut region| region.constrain_equal(p_terminals.input_re.cell(), input_re.cell()),
+    )?;
+    // PUTCHAR prints exactly the output table
+    // iff both running evaluations end up with the same value
+    layouter.assign_region(
+        || "RE: processor table and output table",
+        |mut region| region.constrain_equal(p_terminals.output_re.cell(), output_re.cell()),
+    )
+}
+
+/// Number of rows halo2 reserves for blinding factors at the end of the domain of `C`.
+pub(crate) fn reserved_rows<F: FieldExt, C: Circuit<F>>() -> usize {
+    let mut cs = ConstraintSystem::default();
+    C::configure(&mut cs);
+    cs.blinding_factors() + 1
+}
+
+/// Number of rows the tables of `C` can use in a domain of 2^k rows.
+pub(crate) fn usable_rows<F: FieldExt, C: Circuit<F>>(k: u32) -> usize {
+    (1usize << k).saturating_sub(reserved_rows::<F, C>())
+}
+
+/// Check that the trace ends with a processor row whose cell is in the memory table, padding
+/// repeats both. Traces of the interpreter always do, an empty one is not the trace of a program.
+pub(crate) fn check_trace(matrix: &Matrix) -> std::result::Result<(), String> {
+    let last = matrix
+        .processor_matrix
+        .last()
+        .ok_or("the processor table is empty")?;
+    if !matrix
+        .memory_matrix
+        .iter()
+        .any(|row| row.memory_pointer == last.memory_pointer)
+    {
+        return Err(format!(
+            "the memory table misses cell {} of the last processor row",
+            to_u64(last.memory_pointer)
+        ));
+    }
+    Ok(())
+}
 
-    fn load_table(&self, layouter: &mut impl Layouter<Fq>, matrix: &Matrix) -> Result<(), Error> {
-        self.p_config.load_table(layouter, matrix)?;
-        self.m_config.load_table(layouter, matrix)?;
-        self.i_config.load_table(layouter, matrix)
+/// Check that `rows` usable rows fit into a domain of 2^k rows of `C`.
+pub(crate) fn check_rows<F: FieldExt, C: Circuit<F>>(rows: usize, k: u32) -> error::Result<()> {
+    let min_k = (rows + reserved_rows::<F, C>())
+        .next_power_of_two()
+        .trailing_zeros();
+    if k > MAX_K || min_k > MAX_K {
+        return Err(error::Error::CircuitTooLarge { rows, max_k: MAX_K });
+    }
+    if min_k > k {
+        return Err(error::Error::TraceDoesNotFit { rows, k, min_k });
     }
+    Ok(())
+}
+
+/// Range-check the values, the clk increases and the pointer jumps of a memory table group.
+pub(crate) fn lookup_memory<F: FieldExt, const RANGE: usize>(
+    cs: &mut ConstraintSystem<F>,
+    m_config: MemoryTableConfig,
+    lookup_table: RangeTableConfig<RANGE>,
+) {
+    // The range table is shared with the processor table
+    cs.lookup("Range-Check: memory mv are within 0-255", |vc| {
+        let s_lookup = vc.query_selector(m_config.s_lookup);
+        let mv = vc.query_advice(m_config.mv, Rotation::cur());
+        vec![(s_lookup * mv, lookup_table.table)]
+    });
+
+    // Bytes of the clk increase within a cell, the sum of bytes stays far below the modulus
+    for dclk in m_config.dclk {
+        cs.lookup("Range-Check: memory dclk are within 0-255", |vc| {
+            let s_lookup = vc.query_selector(m_config.s_lookup);
+            let dclk = vc.query_advice(dclk, Rotation::cur());
+            vec![(s_lookup * dclk, lookup_table.table)]
+        });
+    }
+
+    // SHR and SHL jump by at most 255 cells, so the sorted memory table does too
+    cs.lookup("Range-Check: memory mp increases by 0-255", |vc| {
+        let s_m = vc.query_selector(m_config.s_m);
+        let cur_mp = vc.query_advice(m_config.mp, Rotation::cur());
+        let next_mp = vc.query_advice(m_config.mp, Rotation::next());
+        vec![(s_m * (next_mp - cur_mp), lookup_table.table)]
+    });
+}
+
+/// Look up every row of a processor table group in the program table.
+pub(crate) fn lookup_program<F: FieldExt, const RANGE: usize>(
+    cs: &mut ConstraintSystem<F>,
+    p_config: ProcessorTableConfig<RANGE>,
+    program_config: ProgramTableConfig,
+) {
+    // The program table holds (ip, 0, 0) past the last word, so the halting
+    // rows of the processor table are looked up as well. A lookup table is
+    // a single set of columns, so the rows are looked up in the program table
+    // rather than in an instruction table that would grow with the trace.
+    cs.lookup_any(
+        "Program: processor (ip, ci, ni) is an instruction of the program",
+        |vc| {
+            let s_processor = vc.query_selector(p_config.s_lookup);
+            let s_program = vc.query_selector(program_config.s_lookup);
+            let p_ip = vc.query_advice(p_config.ip, Rotation::cur());
+            let p_ci = vc.query_advice(p_config.ci, Rotation::cur());
+            let p_ni = vc.query_advice(p_config.ni, Rotation::cur());
+            let program_ip = vc.query_advice(program_config.ip, Rotation::cur());
+            let program_ci = vc.query_advice(program_config.ci, Rotation::cur());
+            let program_ni = vc.query_advice(program_config.ni, Rotation::cur());
+            vec![
+                (s_processor.clone() * p_ip, s_program.clone() * program_ip),
+                (s_processor.clone() * p_ci, s_program.clone() * program_ci),
+                (s_processor * p_ni, s_program * program_ni),
+            ]
+        },
+    );
 }
 
 #[derive(Default)]
-pub struct MyCircuit<F: Field, const RANGE: usize> {
+pub struct MyCircuit<F: FieldExt, const RANGE: usize, const GROUPS: usize = 1> {
     _marker: PhantomData<F>,
     matrix: Matrix,
+    k: u32,
 }
 
-impl<const RANGE: usize> MyCircuit<Fq, RANGE> {
-    pub fn new(matrix: Matrix) -> Self {
-        Self {
+impl<F: FieldExt, const RANGE: usize, const GROUPS: usize> MyCircuit<F, RANGE, GROUPS> {
+    /// Create the circuit for a domain of 2^k rows, the tables are padded to fill it.
+    pub fn new(mut matrix: Matrix, k: u32) -> error::Result<Self> {
+        check_trace(&matrix).map_err(|message| error::Error::Compile {
+            position: 0,
+            message,
+        })?;
+        check_rows::<F, Self>(Self::rows(&matrix), k)?;
+        pad(&mut matrix, Self::usable_rows(k), GROUPS);
+        Ok(Self {
             _marker: PhantomData,
             matrix,
+            k,
+        })
+    }
+
+    /// The circuit has 2^k rows.
+    pub fn k(&self) -> u32 {
+        self.k
+    }
+
+    /// The trace, padded to the usable rows of the domain.
+    pub fn matrix(&self) -> &Matrix {
+        &self.matrix
+    }
+
+    /// Number of rows the tables can use in a domain of 2^k rows,
+    /// halo2 reserves the last ones for blinding factors.
+    pub fn usable_rows(k: u32) -> usize {
+        usable_rows::<F, Self>(k)
+    }
+
+    /// Number of usable rows the tables of the trace need.
+    pub fn rows(matrix: &Matrix) -> usize {
+        // Every group but the first starts with the last row of the previous one
+        let folded = |len: usize| (len.saturating_sub(1) + GROUPS - 1) / GROUPS + 1;
+        // The program, input and output tables need a row past their content:
+        // a halting row for the former, a header row for the latter
+        [
+            folded(matrix.processor_matrix.len()),
+            folded(matrix.memory_matrix.len()),
+            program::<Fq>(matrix).len() + 1,
+            matrix.input_matrix.len() + 1,
+            matrix.output_matrix.len() + 1,
+            RANGE,
+            OPCODES.len() + 1,
+        ]
+        .into_iter()
+        .max()
+        .unwrap()
+    }
+
+    /// Smallest k whose usable rows hold every table of the trace, up to `MAX_K`.
+    pub fn min_k(matrix: &Matrix) -> error::Result<u32> {
+        let rows = Self::rows(matrix);
+        let k = (rows + reserved_rows::<F, Self>())
+            .next_power_of_two()
+            .trailing_zeros();
+        if k > MAX_K {
+            return Err(error::Error::CircuitTooLarge { rows, max_k: MAX_K });
         }
+        Ok(k)
+    }
+
+    /// Public instances of the circuit, see `public_instances`.
+    pub fn instances(&self) -> Vec<Vec<F>> {
+        let to_fields = |values: &[Fq]| -> Vec<F> { values.iter().map(|v| to_field(*v)).collect() };
+        public_instances(
+            program(&self.matrix),
+            to_fields(&self.matrix.input_matrix),
+            to_fields(&self.matrix.output_matrix),
+        )
     }
 }
 
-// It would be nice if we can use generic type here
-// impl <F:Field> Circuit<F> for MyCircuit<F> {...}
-impl<const RANGE: usize> Circuit<Fq> for MyCircuit<Fq, RANGE> {
-    type Config = MainConfig<RANGE>;
+/// Public instances in the order the instance columns are created: the compiled program,
+/// the bytes read by GETCHAR and the bytes printed by PUTCHAR. The input and output bytes
+/// are preceded by their number. A verifier computes them without the trace.
+pub fn public_instances<F: FieldExt>(
+    program: Vec<F>,
+    input: Vec<F>,
+    output: Vec<F>,
+) -> Vec<Vec<F>> {
+    let with_len = |values: Vec<F>| -> Vec<F> {
+        std::iter::once(F::from(values.len() as u64))
+            .chain(values)
+            .collect()
+    };
+    vec![program, with_len(input), with_len(output)]
+}
+
+impl<F: FieldExt, const RANGE: usize, const GROUPS: usize> Circuit<F>
+    for MyCircuit<F, RANGE, GROUPS>
+{
+    type Config = MainConfig<RANGE, GROUPS>;
     type FloorPlanner = SimpleFloorPlanner;
 
     fn without_witnesses(&self) -> Self {
         Self::default()
     }
 
-    fn configure(meta: &mut ConstraintSystem<Fq>) -> Self::Config {
-        MainConfig::configure(meta)
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let challenges = Challenges::construct(meta);
+        MainConfig::configure(meta, challenges)
     }
 
     fn synthesize(
         &self,
         config: Self::Config,
-        mut layouter: impl Layouter<Fq>,
+        mut layouter: impl Layouter<F>,
     ) -> Result<(), Error> {
         config.load_table(&mut layouter, &self.matrix)?;
         Ok(())
@@ -1,43 +1,100 @@
 use crate::utils::*;
 use ckb_bf_zkvm::matrix::Matrix;
 
-use halo2_proofs::circuit::{Layouter, Value};
-use halo2_proofs::halo2curves::bn256::Fq;
+use halo2_proofs::arithmetic::FieldExt;
+use halo2_proofs::circuit::{AssignedCell, Layouter, Value};
 use halo2_proofs::plonk::*;
 use halo2_proofs::poly::Rotation;
 
+/// Number of bytes of the clk increase within a cell, enough for any trace up to `MAX_K`.
+pub const CLK_LIMBS: usize = 4;
+
 #[derive(Clone, Debug, Copy)]
 pub struct MemoryTableConfig {
     clk: Column<Advice>,
-    mp: Column<Advice>,
-    mv: Column<Advice>,
-    s_m: Selector, // Selector for condition M category (Memory Table)
+    pub(crate) mp: Column<Advice>,
+    pub(crate) mv: Column<Advice>,
+    dmpi: Column<Advice>, // Inverse of mp_{i+1} - mp_i, or 0 if mp is unchanged
+    prp: Column<Advice>,  // Running product of (clk, mp, mv), links to the processor table
+    pub(crate) dclk: [Column<Advice>; CLK_LIMBS], // Bytes of clk_{i+1} - clk_i - 1 within a cell
+    pub(crate) s_lookup: Selector, // Selector for the range-check of mv, enabled on every row
+    pub(crate) s_m: Selector, // Selector for condition M category (Memory Table) and mp jumps
+    s_b: Selector,        // Selector for condition boundary constraints (first row)
+    pub(crate) segment: Option<SegmentColumns>,
+    challenges: Challenges,
+}
+
+/// Columns of a memory table that continues an execution, see `configure_group`.
+#[derive(Clone, Debug, Copy)]
+pub struct SegmentColumns {
+    first: Column<Advice>,              // 1 on the first row of every cell
+    last: Column<Advice>,               // 1 on the last row of every cell
+    pub(crate) start_v: Column<Advice>, // Value of the cell when the segment starts
+    erp: Column<Advice>, // Inverse running product of (mp, mv) over the last rows, links to the image table
+    s_end: Selector,     // Selector for the last row
+}
+
+impl<F: FieldExt> Config<F> for MemoryTableConfig {
+    fn configure(cs: &mut ConstraintSystem<F>, challenges: Challenges) -> Self {
+        Self::configure_group(cs, challenges, false)
+    }
+
+    fn load_table(&self, layouter: &mut impl Layouter<F>, matrix: &Matrix) -> Result<(), Error> {
+        self.assign_group(layouter, matrix, 0, height(matrix, 1))
+            .map(|_| ())
+    }
 }
 
-impl Config for MemoryTableConfig {
-    fn configure(cs: &mut ConstraintSystem<Fq>) -> Self {
-        let one = Expression::Constant(Fq::one());
+impl MemoryTableConfig {
+    /**
+     * Configure a column group of the memory table. The boundary constraints are only enabled in
+     * the first group.
+     *
+     * With `segment` the table continues an execution, so cells do not start at 0: the first row
+     * of every cell holds its start value, which the segment config looks up in the image table
+     * (start_v), and the last rows are accumulated into erp so that the image table can check the
+     * end values. A segment is a single column group.
+     */
+    pub fn configure_group<F: FieldExt>(
+        cs: &mut ConstraintSystem<F>,
+        challenges: Challenges,
+        segment: bool,
+    ) -> Self {
+        let one = Expression::Constant(F::one());
 
         let clk = cs.advice_column();
         let mp = cs.advice_column();
         let mv = cs.advice_column();
-        let s_m = cs.selector();
+        let dmpi = cs.advice_column();
+        let prp = cs.advice_column_in(SecondPhase);
+        let dclk = [(); CLK_LIMBS].map(|_| cs.advice_column());
+        // The rows are copied from the last row of the previous group
+        for column in [clk, mp, mv, prp] {
+            cs.enable_equality(column);
+        }
+        let s_lookup = cs.complex_selector();
+        let s_m = cs.complex_selector();
+        let s_b = cs.selector();
 
+        // The memory pointer increases by 0 to 255, which the main config range-checks:
+        // SHR and SHL jump by at most 255, so no gap between visited cells is larger
         cs.create_gate(
-            "M0: memory pointer either increase by one or by zero",
+            "M0: dmpi is 0 or the inverse of the memory pointer increase",
             |vc| {
                 let cur_mp = vc.query_advice(mp, Rotation::cur());
                 let next_mp = vc.query_advice(mp, Rotation::next());
+                let dmpi = vc.query_advice(dmpi, Rotation::cur());
                 let s_m = vc.query_selector(s_m);
+                let dmp = next_mp - cur_mp;
                 vec![
-                    s_m * (next_mp.clone() - cur_mp.clone() - one.clone())
-                        * (next_mp.clone() - cur_mp.clone()),
+                    s_m.clone() * dmp.clone() * (dmp.clone() * dmpi.clone() - one.clone()),
+                    s_m * dmpi.clone() * (dmp * dmpi - one.clone()),
                 ]
             },
         );
 
         cs.create_gate(
-            "M1: If cur_mp <= next_mp and cur_mv = next_mv, clk must only increase by one.",
+            "M1: If cur_mp = next_mp and cur_mv != next_mv, clk must only increase by one.",
             |vc| {
                 let cur_mp = vc.query_advice(mp, Rotation::cur());
                 let next_mp = vc.query_advice(mp, Rotation::next());
@@ -45,54 +102,371 @@ impl Config for MemoryTableConfig {
                 let next_mv = vc.query_advice(mv, Rotation::next());
                 let cur_clk = vc.query_advice(clk, Rotation::cur());
                 let next_clk = vc.query_advice(clk, Rotation::next());
+                let dmpi = vc.query_advice(dmpi, Rotation::cur());
                 let s_m = vc.query_selector(s_m);
                 vec![
-                    s_m * (next_mp.clone() - cur_mp.clone() - one.clone())
+                    s_m * (one.clone() - (next_mp - cur_mp) * dmpi)
                         * (cur_mv.clone() - next_mv.clone())
                         * (next_clk - cur_clk.clone() - one.clone()),
                 ]
             },
         );
 
+        // The main config range-checks the bytes, so within a cell clk strictly increases.
+        // Otherwise a stale value could be moved before a later write to the same cell.
         cs.create_gate(
-            "M2: If mp increases by 1, then mv must be set to zero.",
+            "M5: If cur_mp = next_mp, next_clk - cur_clk - 1 is the sum of the dclk bytes",
             |vc| {
+                let cur_mp = vc.query_advice(mp, Rotation::cur());
+                let next_mp = vc.query_advice(mp, Rotation::next());
+                let cur_clk = vc.query_advice(clk, Rotation::cur());
+                let next_clk = vc.query_advice(clk, Rotation::next());
+                let dmpi = vc.query_advice(dmpi, Rotation::cur());
+                let s_m = vc.query_selector(s_m);
+                let dclk = dclk
+                    .iter()
+                    .rev()
+                    .fold(Expression::Constant(F::zero()), |expr, limb| {
+                        expr * Expression::Constant(F::from(256))
+                            + vc.query_advice(*limb, Rotation::cur())
+                    });
+                vec![
+                    s_m * (one.clone() - (next_mp - cur_mp) * dmpi)
+                        * (next_clk - cur_clk - one.clone() - dclk),
+                ]
+            },
+        );
+
+        if !segment {
+            cs.create_gate("M2: If mp increases, then mv must be set to zero.", |vc| {
                 let cur_mp = vc.query_advice(mp, Rotation::cur());
                 let next_mp = vc.query_advice(mp, Rotation::next());
                 let next_mv = vc.query_advice(mv, Rotation::next());
                 let s_m = vc.query_selector(s_m);
                 vec![s_m * (next_mp.clone() - cur_mp.clone()) * (next_mv)]
+            });
+
+            // The memory table starts where the processor table starts. Together with the
+            // prp it also ends where the processor table ends: both tables hold the same
+            // rows, so the memory table is never empty.
+            cs.create_gate("MB0: clk_0 = 0", |vc| {
+                let s_b = vc.query_selector(s_b);
+                let clk = vc.query_advice(clk, Rotation::cur());
+                vec![s_b * clk]
+            });
+
+            cs.create_gate("MB1: mp_0 = 0", |vc| {
+                let s_b = vc.query_selector(s_b);
+                let mp = vc.query_advice(mp, Rotation::cur());
+                vec![s_b * mp]
+            });
+
+            cs.create_gate("MB2: mv_0 = 0", |vc| {
+                let s_b = vc.query_selector(s_b);
+                let mv = vc.query_advice(mv, Rotation::cur());
+                vec![s_b * mv]
+            });
+        }
+
+        cs.create_gate(
+            "M3: prp_0 = beta - (clk_0 + gamma * mp_0 + gamma^2 * mv_0)",
+            |vc| {
+                let s_b = vc.query_selector(s_b);
+                let beta = vc.query_challenge(challenges.beta);
+                let gamma = vc.query_challenge(challenges.gamma);
+                let clk = vc.query_advice(clk, Rotation::cur());
+                let mp = vc.query_advice(mp, Rotation::cur());
+                let mv = vc.query_advice(mv, Rotation::cur());
+                let prp = vc.query_advice(prp, Rotation::cur());
+                vec![s_b * (prp - (beta - (clk + gamma.clone() * mp + gamma.clone() * gamma * mv)))]
+            },
+        );
+
+        cs.create_gate(
+            "M4: prp_{i+1} = prp_i * (beta - (clk_{i+1} + gamma * mp_{i+1} + gamma^2 * mv_{i+1}))",
+            |vc| {
+                let s_m = vc.query_selector(s_m);
+                let beta = vc.query_challenge(challenges.beta);
+                let gamma = vc.query_challenge(challenges.gamma);
+                let next_clk = vc.query_advice(clk, Rotation::next());
+                let next_mp = vc.query_advice(mp, Rotation::next());
+                let next_mv = vc.query_advice(mv, Rotation::next());
+                let cur_prp = vc.query_advice(prp, Rotation::cur());
+                let next_prp = vc.query_advice(prp, Rotation::next());
+                vec![
+                    s_m * (next_prp
+                        - cur_prp
+                            * (beta
+                                - (next_clk
+                                    + gamma.clone() * next_mp
+                                    + gamma.clone() * gamma * next_mv))),
+                ]
+            },
+        );
+
+        let segment = segment
+            .then(|| Self::configure_segment(cs, challenges, mp, mv, dmpi, s_m, s_b, s_lookup));
+
+        Self {
+            clk,
+            mp,
+            mv,
+            dmpi,
+            prp,
+            dclk,
+            s_lookup,
+            s_m,
+            s_b,
+            segment,
+            challenges,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn configure_segment<F: FieldExt>(
+        cs: &mut ConstraintSystem<F>,
+        challenges: Challenges,
+        mp: Column<Advice>,
+        mv: Column<Advice>,
+        dmpi: Column<Advice>,
+        s_m: Selector,
+        s_b: Selector,
+        s_lookup: Selector,
+    ) -> SegmentColumns {
+        let one = Expression::Constant(F::one());
+
+        let first = cs.advice_column();
+        let last = cs.advice_column();
+        let start_v = cs.advice_column();
+        let erp = cs.advice_column_in(SecondPhase);
+        cs.enable_equality(erp);
+        let s_end = cs.selector();
+
+        cs.create_gate("MS0: first_0 = 1", |vc| {
+            let s_b = vc.query_selector(s_b);
+            let first = vc.query_advice(first, Rotation::cur());
+            vec![s_b * (first - one.clone())]
+        });
+
+        // The table is sorted by mp, so a cell ends iff mp changes
+        cs.create_gate(
+            "MS1: last is 1 iff mp increases, then the next row is the first of a cell",
+            |vc| {
+                let s_m = vc.query_selector(s_m);
+                let cur_mp = vc.query_advice(mp, Rotation::cur());
+                let next_mp = vc.query_advice(mp, Rotation::next());
+                let dmpi = vc.query_advice(dmpi, Rotation::cur());
+                let cur_last = vc.query_advice(last, Rotation::cur());
+                let next_first = vc.query_advice(first, Rotation::next());
+                vec![
+                    s_m.clone() * (cur_last.clone() - (next_mp - cur_mp) * dmpi),
+                    s_m * (next_first - cur_last),
+                ]
+            },
+        );
+
+        cs.create_gate("MS2: last_end = 1", |vc| {
+            let s_end = vc.query_selector(s_end);
+            let last = vc.query_advice(last, Rotation::cur());
+            vec![s_end * (last - one.clone())]
+        });
+
+        cs.create_gate("MS3: If first = 1, then mv is the start value", |vc| {
+            let s_lookup = vc.query_selector(s_lookup);
+            let first = vc.query_advice(first, Rotation::cur());
+            let mv = vc.query_advice(mv, Rotation::cur());
+            let start_v = vc.query_advice(start_v, Rotation::cur());
+            vec![s_lookup * first * (mv - start_v)]
+        });
+
+        // Rows that are not the last of their cell have a factor of 1
+        let factor = |vc: &mut VirtualCells<'_, F>, rotation: Rotation| {
+            let beta = vc.query_challenge(challenges.beta);
+            let gamma = vc.query_challenge(challenges.gamma);
+            let mp = vc.query_advice(mp, rotation);
+            let mv = vc.query_advice(mv, rotation);
+            let last = vc.query_advice(last, rotation);
+            last.clone() * (beta - (mp + gamma * mv)) + one.clone() - last
+        };
+
+        cs.create_gate(
+            "MS4: erp_0 * (beta - (mp_0 + gamma * mv_0)) = 1 if last_0 = 1",
+            |vc| {
+                let s_b = vc.query_selector(s_b);
+                let erp = vc.query_advice(erp, Rotation::cur());
+                vec![s_b * (erp * factor(vc, Rotation::cur()) - one.clone())]
+            },
+        );
+
+        cs.create_gate(
+            "MS5: erp_{i+1} * (beta - (mp_{i+1} + gamma * mv_{i+1})) = erp_i if last_{i+1} = 1",
+            |vc| {
+                let s_m = vc.query_selector(s_m);
+                let cur_erp = vc.query_advice(erp, Rotation::cur());
+                let next_erp = vc.query_advice(erp, Rotation::next());
+                vec![s_m * (next_erp * factor(vc, Rotation::next()) - cur_erp)]
             },
         );
-        Self { clk, mp, mv, s_m }
+
+        SegmentColumns {
+            first,
+            last,
+            start_v,
+            erp,
+            s_end,
+        }
     }
 
-    fn load_table(&self, layouter: &mut impl Layouter<Fq>, matrix: &Matrix) -> Result<(), Error> {
+    /// Load the rows of column group `group` into a region of `height` rows, like the processor
+    /// table does, and return the cells of its first and last row and the last cell of the
+    /// running product, which the main config constrains to be equal to the processor table's one.
+    pub fn assign_group<F: FieldExt>(
+        &self,
+        layouter: &mut impl Layouter<F>,
+        matrix: &Matrix,
+        group: usize,
+        height: usize,
+    ) -> Result<(GroupCells<F>, MemoryTerminals<F>), Error> {
+        let beta = layouter.get_challenge(self.challenges.beta);
+        let gamma = layouter.get_challenge(self.challenges.gamma);
         layouter.assign_region(
-            || "Load Memory Table",
+            || region_name("Load Memory Table", group),
             |mut region| {
                 let memory_matrix = &matrix.memory_matrix;
-                for (idx, row) in memory_matrix.iter().enumerate() {
-                    if idx < memory_matrix.len() - 1 {
+                let start = group * (height - 1);
+                let mut prp = Value::known(F::one());
+                let mut erp = Value::known(F::one());
+                let mut start_v = F::zero();
+                let mut first = Vec::new();
+                let mut last = Vec::new();
+                let mut terminals = None;
+                if group == 0 {
+                    // B condition is enabled only for the first row
+                    self.s_b.enable(&mut region, 0)?;
+                }
+                // The running product of a group carries on from the rows before it
+                for (idx, row) in memory_matrix[..start + height].iter().enumerate() {
+                    prp = prp
+                        * (beta
+                            - (Value::known(to_field(row.cycle))
+                                + gamma * Value::known(to_field(row.memory_pointer))
+                                + gamma * gamma * Value::known(to_field(row.memory_value))));
+                    if idx < start {
+                        continue;
+                    }
+
+                    let offset = idx - start;
+                    if offset < height - 1 {
                         // M condition is enabled except last row
-                        self.s_m.enable(&mut region, idx)?;
+                        self.s_m.enable(&mut region, offset)?;
                     }
-                    region.assign_advice(|| "clk", self.clk, idx, || Value::known(row.cycle))?;
-                    region.assign_advice(
-                        || "mp",
-                        self.mp,
-                        idx,
-                        || Value::known(row.memory_pointer),
-                    )?;
+                    // Enable lookup
+                    self.s_lookup.enable(&mut region, offset)?;
+                    let mut cells = vec![
+                        region.assign_advice(
+                            || "clk",
+                            self.clk,
+                            offset,
+                            || Value::known(to_field(row.cycle)),
+                        )?,
+                        region.assign_advice(
+                            || "mp",
+                            self.mp,
+                            offset,
+                            || Value::known(to_field(row.memory_pointer)),
+                        )?,
+                        region.assign_advice(
+                            || "mv",
+                            self.mv,
+                            offset,
+                            || Value::known(to_field(row.memory_value)),
+                        )?,
+                    ];
+                    let dmp = memory_matrix.get(idx + 1).map_or(F::zero(), |next| {
+                        to_field::<F>(next.memory_pointer) - to_field::<F>(row.memory_pointer)
+                    });
                     region.assign_advice(
-                        || "mv",
-                        self.mv,
-                        idx,
-                        || Value::known(row.memory_value),
+                        || "dmpi",
+                        self.dmpi,
+                        offset,
+                        || Value::known(dmp.invert().unwrap_or(F::zero())),
                     )?;
+                    let dclk = match memory_matrix.get(idx + 1) {
+                        // Wraps around if the rows are out of order, which M5 rejects
+                        Some(next) if next.memory_pointer == row.memory_pointer => {
+                            to_u64(next.cycle).wrapping_sub(to_u64(row.cycle) + 1)
+                        }
+                        _ => 0,
+                    };
+                    for (limb, column) in self.dclk.iter().enumerate() {
+                        region.assign_advice(
+                            || "dclk",
+                            *column,
+                            offset,
+                            || Value::known(F::from((dclk >> (8 * limb)) & 0xff)),
+                        )?;
+                    }
+                    let cell = region.assign_advice(|| "prp", self.prp, offset, || prp)?;
+                    cells.push(cell.clone());
+                    if offset == 0 {
+                        first = cells.clone();
+                    }
+                    last = cells;
+                    let erp_cell = match self.segment {
+                        Some(segment) => {
+                            let is_first = idx == 0
+                                || memory_matrix[idx - 1].memory_pointer != row.memory_pointer;
+                            let is_last = dmp != F::zero() || offset == height - 1;
+                            if is_first {
+                                start_v = to_field(row.memory_value);
+                            }
+                            if is_last {
+                                erp = erp
+                                    * (beta
+                                        - (Value::known(to_field(row.memory_pointer))
+                                            + gamma * Value::known(to_field(row.memory_value))))
+                                    .map(|factor| factor.invert().unwrap_or(F::zero()));
+                            }
+                            if offset == height - 1 {
+                                segment.s_end.enable(&mut region, offset)?;
+                            }
+                            let flag = |value: bool| Value::known(F::from(value as u64));
+                            region.assign_advice(
+                                || "first",
+                                segment.first,
+                                offset,
+                                || flag(is_first),
+                            )?;
+                            region.assign_advice(
+                                || "last",
+                                segment.last,
+                                offset,
+                                || flag(is_last),
+                            )?;
+                            region.assign_advice(
+                                || "start_v",
+                                segment.start_v,
+                                offset,
+                                || Value::known(start_v),
+                            )?;
+                            Some(region.assign_advice(|| "erp", segment.erp, offset, || erp)?)
+                        }
+                        None => None,
+                    };
+                    terminals = Some(MemoryTerminals {
+                        prp: cell,
+                        erp: erp_cell,
+                    });
                 }
-                Ok(())
+                let terminals = terminals.ok_or(Error::Synthesis)?;
+                Ok((GroupCells { first, last }, terminals))
             },
         )
     }
 }
+
+/// Last cells of the memory table's cross-table arguments, erp only in a segment
+pub struct MemoryTerminals<F: FieldExt> {
+    pub prp: AssignedCell<F, F>,
+    pub erp: Option<AssignedCell<F, F>>,
+}
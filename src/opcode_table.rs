@@ -0,0 +1,40 @@
+use crate::utils::*;
+use ckb_bf_zkvm::matrix::Matrix;
+
+use halo2_proofs::arithmetic::FieldExt;
+use halo2_proofs::circuit::{Layouter, Value};
+use halo2_proofs::plonk::*;
+
+#[derive(Clone, Debug, Copy)]
+pub struct OpcodeTableConfig {
+    pub table: TableColumn,
+}
+
+impl<F: FieldExt> Config<F> for OpcodeTableConfig {
+    fn configure(cs: &mut ConstraintSystem<F>, _: Challenges) -> Self {
+        let table = cs.lookup_table_column();
+        Self { table }
+    }
+
+    fn load_table(&self, layouter: &mut impl Layouter<F>, _: &Matrix) -> Result<(), Error> {
+        layouter.assign_table(
+            || "load opcode table",
+            |mut table| {
+                // 0 is the opcode of the halting row
+                table.assign_cell(|| "halt", self.table, 0, || Value::known(F::zero()))?;
+                let mut offset = 1;
+                for op in OPCODES {
+                    table.assign_cell(
+                        || "opcode",
+                        self.table,
+                        offset,
+                        || Value::known(F::from(op as u64)),
+                    )?;
+                    offset += 1;
+                }
+
+                Ok(())
+            },
+        )
+    }
+}
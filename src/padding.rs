@@ -0,0 +1,61 @@
+use crate::utils::program;
+use ckb_bf_zkvm::matrix::Matrix;
+use halo2_proofs::halo2curves::bn256::Fq;
+
+/**
+ * Extend the processor and memory tables to fill `groups` column groups of `height` rows, so
+ * that every table of the circuit spans the same rows and selectors are enabled regardless of
+ * the trace. Every group but the first starts with the last row of the previous one.
+ *
+ * The padding rows repeat the halting row of the processor table (ci = 0, ni = 0) with clk
+ * increasing by one per row. They are inserted into the memory table right after the last
+ * access of the final memory pointer, which keeps it sorted by (mp, clk).
+ * The input and output matrices are left as they are, their tables count the real rows.
+ * `MyCircuit::new` checks that the tables fit before, the assertions only guard the invariants.
+ */
+pub(crate) fn pad(matrix: &mut Matrix, height: usize, groups: usize) {
+    let len = matrix.processor_matrix.len();
+    assert!(len > 0, "the processor table is empty");
+    assert!(
+        program::<Fq>(matrix).len() < height,
+        "the program table does not fit into {} rows",
+        height
+    );
+    assert!(
+        matrix.input_matrix.len() < height && matrix.output_matrix.len() < height,
+        "the input or output table does not fit into {} rows",
+        height
+    );
+    let rows = groups * (height - 1) + 1;
+    assert!(
+        len <= rows,
+        "the processor table does not fit into {} groups of {} rows",
+        groups,
+        height
+    );
+
+    let mut halt = matrix.processor_matrix[len - 1].clone();
+    halt.current_instruction = Fq::from(0);
+    halt.next_instruction = Fq::from(0);
+
+    let pos = matrix
+        .memory_matrix
+        .iter()
+        .rposition(|row| row.memory_pointer == halt.memory_pointer)
+        .expect("the final memory pointer is missing from the memory table");
+    let template = matrix.memory_matrix[pos].clone();
+    let memory_padding = (len..rows).map(|clk| {
+        let mut row = template.clone();
+        row.cycle = Fq::from(clk as u64);
+        row
+    });
+    matrix
+        .memory_matrix
+        .splice(pos + 1..pos + 1, memory_padding);
+
+    for clk in len..rows {
+        let mut reg = halt.clone();
+        reg.cycle = Fq::from(clk as u64);
+        matrix.processor_matrix.push(reg);
+    }
+}
@@ -1,34 +1,65 @@
+use crate::opcode_table::OpcodeTableConfig;
 use crate::range_table::RangeTableConfig;
 use crate::utils::*;
 use ckb_bf_zkvm::matrix::Matrix;
 
-use halo2_proofs::circuit::{Layouter, Value};
-use halo2_proofs::halo2curves::bn256::Fq;
+use halo2_proofs::arithmetic::FieldExt;
+use halo2_proofs::circuit::{AssignedCell, Layouter, Value};
 use halo2_proofs::plonk::*;
 use halo2_proofs::poly::Rotation;
 
 #[derive(Clone, Debug, Copy)]
 pub struct ProcessorTableConfig<const RANGE: usize> {
     clk: Column<Advice>,
-    ip: Column<Advice>,
-    ci: Column<Advice>,
-    ni: Column<Advice>,
+    pub(crate) ip: Column<Advice>,
+    pub(crate) ci: Column<Advice>,
+    pub(crate) ni: Column<Advice>,
     mp: Column<Advice>,
     mv: Column<Advice>,
     mvi: Column<Advice>,
-    lookup_table: RangeTableConfig<RANGE>, // Lookup table ensure mv are within [0-255]
-    s_lookup: Selector,                    // Selector for lookup_table
-    s_p: Selector,                         // Selector for condition P category (Processor Table)
-    s_c: Selector, // Selector for condition C category (Consistency Constraints)
-    s_b: Selector, // Selector for condition B category (Boundary Constraints)
+    acc: Column<Advice>, // Value cleared by the last CLEAR, which MOVE adds to a cell
+    prp: Column<Advice>, // Running product of (clk, mp, mv), links to the memory table
+    input_re: Column<Advice>, // Running evaluation of GETCHAR results, links to the input table
+    output_re: Column<Advice>, // Running evaluation of PUTCHAR arguments, links to the output table
+    pub(crate) lookup_table: RangeTableConfig<RANGE>, // Lookup table ensure mv are within [0-255]
+    opcode_table: OpcodeTableConfig, // Lookup table ensure ci is an opcode or 0
+    pub(crate) s_lookup: Selector, // Selector for lookup_table, opcode_table and the program table
+    s_p: Selector,       // Selector for condition P category (Processor Table)
+    s_c: Selector,       // Selector for condition C category (Consistency Constraints)
+    s_b: Selector,       // Selector for condition B category (Boundary Constraints)
+    s_h: Selector,       // Selector for the last row of the trace, which must halt
+    challenges: Challenges,
 }
 
-impl<const RANGE: usize> Config for ProcessorTableConfig<RANGE> {
-    fn configure(cs: &mut ConstraintSystem<Fq>) -> Self {
-        let zero = Expression::Constant(Fq::zero());
-        let one = Expression::Constant(Fq::one());
-        let two = Expression::Constant(Fq::from(2));
-        let range_max = Expression::Constant(Fq::from((RANGE - 1) as u64));
+impl<F: FieldExt, const RANGE: usize> Config<F> for ProcessorTableConfig<RANGE> {
+    fn configure(cs: &mut ConstraintSystem<F>, challenges: Challenges) -> Self {
+        let lookup_table = RangeTableConfig::configure(cs, challenges);
+        let opcode_table = OpcodeTableConfig::configure(cs, challenges);
+        Self::configure_group(cs, challenges, lookup_table, opcode_table, true)
+    }
+
+    fn load_table(&self, layouter: &mut impl Layouter<F>, matrix: &Matrix) -> Result<(), Error> {
+        self.assign_group(layouter, matrix, 0, height(matrix, 1), F::zero(), true)
+            .map(|_| ())
+    }
+}
+
+impl<const RANGE: usize> ProcessorTableConfig<RANGE> {
+    /// Configure a column group of the processor table. The lookup tables are shared by
+    /// every group, and the boundary constraints are only enabled in the first one.
+    /// Without `reset` the first row is not constrained to the reset state of the machine
+    /// (B0, B1, B3, B4 and B8), for traces that continue an execution, see `segment`.
+    pub fn configure_group<F: FieldExt>(
+        cs: &mut ConstraintSystem<F>,
+        challenges: Challenges,
+        lookup_table: RangeTableConfig<RANGE>,
+        opcode_table: OpcodeTableConfig,
+        reset: bool,
+    ) -> Self {
+        let zero = Expression::Constant(F::zero());
+        let one = Expression::Constant(F::one());
+        let two = Expression::Constant(F::from(2));
+        let range = Expression::Constant(F::from(RANGE as u64));
 
         let clk = cs.advice_column();
         let ci = cs.advice_column();
@@ -37,34 +68,85 @@ impl<const RANGE: usize> Config for ProcessorTableConfig<RANGE> {
         let mp = cs.advice_column();
         let mv = cs.advice_column();
         let mvi = cs.advice_column();
-        let lookup_table = RangeTableConfig::configure(cs);
+        let acc = cs.advice_column();
+        let prp = cs.advice_column_in(SecondPhase);
+        let input_re = cs.advice_column_in(SecondPhase);
+        let output_re = cs.advice_column_in(SecondPhase);
+        // The state is copied from the last row of the previous group, mvi follows from mv
+        for column in [clk, ip, ci, ni, mp, mv, acc, prp, input_re, output_re] {
+            cs.enable_equality(column);
+        }
         let s_lookup = cs.complex_selector();
         let s_c = cs.selector();
         let s_p = cs.selector();
         let s_b = cs.selector();
+        let s_h = cs.selector();
 
-        cs.create_gate("B0: clk_0 = 0", |vc| {
-            let s_b = vc.query_selector(s_b);
-            let clk = vc.query_advice(clk, Rotation::cur());
-            vec![s_b * clk]
-        });
+        if reset {
+            cs.create_gate("B0: clk_0 = 0", |vc| {
+                let s_b = vc.query_selector(s_b);
+                let clk = vc.query_advice(clk, Rotation::cur());
+                vec![s_b * clk]
+            });
+
+            cs.create_gate("B1: ip_0 = 0", |vc| {
+                let s_b = vc.query_selector(s_b);
+                let ip = vc.query_advice(ip, Rotation::cur());
+                vec![s_b * ip]
+            });
+
+            cs.create_gate("B3: mp_0 = 0", |vc| {
+                let s_b = vc.query_selector(s_b);
+                let mp = vc.query_advice(mp, Rotation::cur());
+                vec![s_b * mp]
+            });
 
-        cs.create_gate("B1: ip_0 = 0", |vc| {
+            cs.create_gate("B4: mv_0 = 0", |vc| {
+                let s_b = vc.query_selector(s_b);
+                let mv = vc.query_advice(mv, Rotation::cur());
+                vec![s_b * mv]
+            });
+        }
+
+        cs.create_gate(
+            "B5: prp_0 = beta - (clk_0 + gamma * mp_0 + gamma^2 * mv_0)",
+            |vc| {
+                let s_b = vc.query_selector(s_b);
+                let beta = vc.query_challenge(challenges.beta);
+                let gamma = vc.query_challenge(challenges.gamma);
+                let clk = vc.query_advice(clk, Rotation::cur());
+                let mp = vc.query_advice(mp, Rotation::cur());
+                let mv = vc.query_advice(mv, Rotation::cur());
+                let prp = vc.query_advice(prp, Rotation::cur());
+                vec![s_b * (prp - (beta - (clk + gamma.clone() * mp + gamma.clone() * gamma * mv)))]
+            },
+        );
+
+        cs.create_gate("B6: input_re_0 = 1", |vc| {
             let s_b = vc.query_selector(s_b);
-            let ip = vc.query_advice(ip, Rotation::cur());
-            vec![s_b * ip]
+            let input_re = vc.query_advice(input_re, Rotation::cur());
+            vec![s_b * (input_re - one.clone())]
         });
 
-        cs.create_gate("B3: mp_0 = 0", |vc| {
+        cs.create_gate("B7: output_re_0 = 1", |vc| {
             let s_b = vc.query_selector(s_b);
-            let mp = vc.query_advice(mp, Rotation::cur());
-            vec![s_b * mp]
+            let output_re = vc.query_advice(output_re, Rotation::cur());
+            vec![s_b * (output_re - one.clone())]
         });
 
-        cs.create_gate("B4: mv_0 = 0", |vc| {
-            let s_b = vc.query_selector(s_b);
-            let mv = vc.query_advice(mv, Rotation::cur());
-            vec![s_b * mv]
+        if reset {
+            cs.create_gate("B8: acc_0 = 0", |vc| {
+                let s_b = vc.query_selector(s_b);
+                let acc = vc.query_advice(acc, Rotation::cur());
+                vec![s_b * acc]
+            });
+        }
+
+        // P_7 only keeps a halting row halting, without B9 a proof could stop at any row
+        cs.create_gate("B9: ci_last = 0", |vc| {
+            let s_h = vc.query_selector(s_h);
+            let ci = vc.query_advice(ci, Rotation::cur());
+            vec![s_h * ci]
         });
 
         cs.lookup("Range-Check: mv are within 0-255", |vc| {
@@ -73,6 +155,13 @@ impl<const RANGE: usize> Config for ProcessorTableConfig<RANGE> {
             vec![(s_lookup * mv, lookup_table.table)]
         });
 
+        // Otherwise an unknown ci could satisfy P_1 - P_3 with a mix of deselectors
+        cs.lookup("Opcode: ci is an opcode or 0", |vc| {
+            let s_lookup = vc.query_selector(s_lookup);
+            let ci = vc.query_advice(ci, Rotation::cur());
+            vec![(s_lookup * ci, opcode_table.table)]
+        });
+
         cs.create_gate("P0: clk increase one per step", |vc| {
             let s_p = vc.query_selector(s_p);
             let cur_clk = vc.query_advice(clk, Rotation::cur());
@@ -80,6 +169,28 @@ impl<const RANGE: usize> Config for ProcessorTableConfig<RANGE> {
             vec![s_p * (next_clk - cur_clk - one.clone())]
         });
 
+        cs.create_gate(
+            "P_4: prp_{i+1} = prp_i * (beta - (clk_{i+1} + gamma * mp_{i+1} + gamma^2 * mv_{i+1}))",
+            |vc| {
+                let s_p = vc.query_selector(s_p);
+                let beta = vc.query_challenge(challenges.beta);
+                let gamma = vc.query_challenge(challenges.gamma);
+                let next_clk = vc.query_advice(clk, Rotation::next());
+                let next_mp = vc.query_advice(mp, Rotation::next());
+                let next_mv = vc.query_advice(mv, Rotation::next());
+                let cur_prp = vc.query_advice(prp, Rotation::cur());
+                let next_prp = vc.query_advice(prp, Rotation::next());
+                vec![
+                    s_p * (next_prp
+                        - cur_prp
+                            * (beta
+                                - (next_clk
+                                    + gamma.clone() * next_mp
+                                    + gamma.clone() * gamma * next_mv))),
+                ]
+            },
+        );
+
         cs.create_gate("C0: mv is 0 or mvi is the inverse of mv", |vc| {
             let s_c = vc.query_selector(s_c);
             let mv = vc.query_advice(mv, Rotation::cur());
@@ -95,12 +206,12 @@ impl<const RANGE: usize> Config for ProcessorTableConfig<RANGE> {
         });
 
         // A deselector for op evalutes to zero iff ci != op
-        let create_deselector = |ci: Expression<Fq>, op| {
+        let create_deselector = |ci: Expression<F>, op| {
             OPCODES.iter().fold(ci.clone(), |expr, v| {
                 if *v == op {
                     expr
                 } else {
-                    expr * (ci.clone() - Expression::Constant(Fq::from(*v as u64)))
+                    expr * (ci.clone() - Expression::Constant(F::from(*v as u64)))
                 }
             })
         };
@@ -117,14 +228,19 @@ impl<const RANGE: usize> Config for ProcessorTableConfig<RANGE> {
             let cur_mvi = vc.query_advice(mvi, Rotation::cur());
             let cur_ni = vc.query_advice(ni, Rotation::cur());
             let s_p = vc.query_selector(s_p);
-            // ADD SUB SHR SHL GETCHAR PUTCHAR share the same p1 condition:
-            // ip increases by 1
+            // ADD SUB SHR SHL share the same p1 condition:
+            // ip increases by 2, ni is the argument of the instruction
             let expr1 = (deselectors[ADD].clone()
                 + deselectors[SUB].clone()
                 + deselectors[SHR].clone()
-                + deselectors[SHL].clone()
-                + deselectors[GETCHAR].clone()
-                + deselectors[PUTCHAR].clone())
+                + deselectors[SHL].clone())
+                * (next_ip.clone() - cur_ip.clone() - two.clone());
+            // GETCHAR PUTCHAR CLEAR MOVE share the same p1 condition:
+            // ip increases by 1
+            let expr2 = (deselectors[GETCHAR].clone()
+                + deselectors[PUTCHAR].clone()
+                + deselectors[CLEAR].clone()
+                + deselectors[MOVE].clone())
                 * (next_ip.clone() - cur_ip.clone() - one.clone());
             // LB: if mv != 0 ⇒ ip increases by 2 and if mv == 0 ⇒ ip is set to ni
             let expr_lb = deselectors[LB].clone()
@@ -136,7 +252,7 @@ impl<const RANGE: usize> Config for ProcessorTableConfig<RANGE> {
                 * ((cur_mv.clone() * cur_mvi.clone() - one.clone())
                     * (next_ip.clone() - cur_ip.clone() - two.clone())
                     + (cur_mv.clone() * (next_ip.clone() - cur_ni.clone())));
-            vec![s_p * (expr1 + expr_lb + expr_rb)]
+            vec![s_p * (expr1 + expr2 + expr_lb + expr_rb)]
         });
 
         cs.create_gate("P_2: instruction mutates state(2) correctly", |vc| {
@@ -148,21 +264,23 @@ impl<const RANGE: usize> Config for ProcessorTableConfig<RANGE> {
             let s_p = vc.query_selector(s_p);
             let cur_mp = vc.query_advice(mp, Rotation::cur());
             let next_mp = vc.query_advice(mp, Rotation::next());
-            // ADD, SUB, LB, RB, GETCHAR, PUTCHAR share the same p2 condition:
+            let cur_ni = vc.query_advice(ni, Rotation::cur());
+            // ADD, SUB, LB, RB, GETCHAR, PUTCHAR, CLEAR, MOVE share the same p2 condition:
             // memory pointer stay at the same
             let expr1 = (deselectors[ADD].clone()
                 + deselectors[SUB].clone()
                 + deselectors[LB].clone()
                 + deselectors[RB].clone()
                 + deselectors[GETCHAR].clone()
-                + deselectors[PUTCHAR].clone())
+                + deselectors[PUTCHAR].clone()
+                + deselectors[CLEAR].clone()
+                + deselectors[MOVE].clone())
                 * (next_mp.clone() - cur_mp.clone());
-            // SHL: mp decreases by one
+            // SHL: mp decreases by ni
             let expr_shl =
-                deselectors[SHL].clone() * (next_mp.clone() - cur_mp.clone() + one.clone());
-            // SHR: mp increases by one
-            let expr_shr =
-                deselectors[SHR].clone() * (next_mp.clone() - cur_mp.clone() - one.clone());
+                deselectors[SHL].clone() * (next_mp.clone() - cur_mp.clone() + cur_ni.clone());
+            // SHR: mp increases by ni
+            let expr_shr = deselectors[SHR].clone() * (next_mp.clone() - cur_mp.clone() - cur_ni);
             vec![s_p * (expr1 + expr_shl + expr_shr)]
         });
 
@@ -175,28 +293,108 @@ impl<const RANGE: usize> Config for ProcessorTableConfig<RANGE> {
             let s_p = vc.query_selector(s_p);
             let cur_mv = vc.query_advice(mv, Rotation::cur());
             let next_mv = vc.query_advice(mv, Rotation::next());
-            // LB, RB, PUTCHAR share the same p3 condition:
+            let cur_ni = vc.query_advice(ni, Rotation::cur());
+            let cur_acc = vc.query_advice(acc, Rotation::cur());
+            // LB, RB, PUTCHAR share the same p3 condition (PUTCHAR is also checked by P_6):
             // memory value stay at the same
             let expr1 =
                 (deselectors[LB].clone() + deselectors[RB].clone() + deselectors[PUTCHAR].clone())
                     * (next_mv.clone() - cur_mv.clone());
-            // note: we have lookup table to ensure all mvs are within [0-255],
-            // therefore, value can only decreases by 255 iff cur_mv=255, next_mv=0
+            // note: we have lookup table to ensure all mvs are within [0-255] and the
+            // compiler keeps ni within [1-255], therefore, at most one factor vanishes:
+            // the wrap bit is whether mv + ni wraps around 256
             // same goes for wrapping_sub
-            // ADD: mv increases by 1, or decreases by 255
+            // ADD: mv increases by ni, or decreases by 256 - ni
             let expr_add = deselectors[ADD].clone()
-                * (next_mv.clone() - cur_mv.clone() - one.clone())
-                * (next_mv.clone() - cur_mv.clone() + range_max.clone());
-            // sub: mv decreases by 1, or increases by 255
+                * (next_mv.clone() - cur_mv.clone() - cur_ni.clone())
+                * (next_mv.clone() - cur_mv.clone() - cur_ni.clone() + range.clone());
+            // sub: mv decreases by ni, or increases by 256 - ni
             let expr_sub = deselectors[SUB].clone()
-                * (next_mv.clone() - cur_mv.clone() + one.clone())
-                * (next_mv.clone() - cur_mv.clone() - range_max.clone());
-            // SHL, SHR, GETCHAR: always true (check elsewhere)
+                * (next_mv.clone() - cur_mv.clone() + cur_ni.clone())
+                * (next_mv.clone() - cur_mv.clone() + cur_ni - range.clone());
+            // CLEAR: mv is set to zero
+            let expr_clear = deselectors[CLEAR].clone() * next_mv.clone();
+            // MOVE: mv increases by acc, or decreases by 256 - acc, acc is a copy of an mv
+            let expr_move = deselectors[MOVE].clone()
+                * (next_mv.clone() - cur_mv.clone() - cur_acc.clone())
+                * (next_mv.clone() - cur_mv.clone() - cur_acc + range.clone());
+            // SHL, SHR, GETCHAR: always true (GETCHAR is checked by P_5)
             let expr2 = (deselectors[SHL].clone()
                 + deselectors[SHR].clone()
                 + deselectors[GETCHAR].clone())
                 * (zero.clone());
-            vec![s_p * (expr1 + expr2 + expr_add + expr_sub)]
+            vec![s_p * (expr1 + expr2 + expr_add + expr_sub + expr_clear + expr_move)]
+        });
+
+        cs.create_gate("P_5: GETCHAR accumulates mv_{i+1} into input_re", |vc| {
+            let ci = vc.query_advice(ci, Rotation::cur());
+            let s_p = vc.query_selector(s_p);
+            let delta = vc.query_challenge(challenges.delta);
+            let next_mv = vc.query_advice(mv, Rotation::next());
+            let cur_re = vc.query_advice(input_re, Rotation::cur());
+            let next_re = vc.query_advice(input_re, Rotation::next());
+            let getchar = Expression::Constant(F::from(OPCODES[GETCHAR] as u64));
+            // GETCHAR: input_re_{i+1} = input_re_i * delta + mv_{i+1}
+            let expr_getchar = create_deselector(ci.clone(), OPCODES[GETCHAR])
+                * (next_re.clone() - (cur_re.clone() * delta + next_mv));
+            // Otherwise: input_re stays the same
+            let expr_others = (ci - getchar) * (next_re - cur_re);
+            vec![s_p * (expr_getchar + expr_others)]
+        });
+
+        cs.create_gate("P_6: PUTCHAR accumulates mv_i into output_re", |vc| {
+            let ci = vc.query_advice(ci, Rotation::cur());
+            let s_p = vc.query_selector(s_p);
+            let epsilon = vc.query_challenge(challenges.epsilon);
+            let cur_mv = vc.query_advice(mv, Rotation::cur());
+            let cur_re = vc.query_advice(output_re, Rotation::cur());
+            let next_re = vc.query_advice(output_re, Rotation::next());
+            let putchar = Expression::Constant(F::from(OPCODES[PUTCHAR] as u64));
+            // PUTCHAR: output_re_{i+1} = output_re_i * epsilon + mv_i
+            let expr_putchar = create_deselector(ci.clone(), OPCODES[PUTCHAR])
+                * (next_re.clone() - (cur_re.clone() * epsilon + cur_mv));
+            // Otherwise: output_re stays the same
+            let expr_others = (ci - putchar) * (next_re - cur_re);
+            vec![s_p * (expr_putchar + expr_others)]
+        });
+
+        // Padding rows repeat the halting row, so a halted machine must stay halted:
+        // otherwise a prover could resume execution anywhere after a halting row
+        cs.create_gate("P_7: the halting row is repeated until the end", |vc| {
+            let ci = vc.query_advice(ci, Rotation::cur());
+            let s_p = vc.query_selector(s_p);
+            // Evaluates to zero iff ci is an opcode, ci is looked up in the opcode table
+            let halt = OPCODES.iter().fold(one.clone(), |expr, v| {
+                expr * (ci.clone() - Expression::Constant(F::from(*v as u64)))
+            });
+            let next_ci = vc.query_advice(ci, Rotation::next());
+            let cur_ip = vc.query_advice(ip, Rotation::cur());
+            let next_ip = vc.query_advice(ip, Rotation::next());
+            let cur_mp = vc.query_advice(mp, Rotation::cur());
+            let next_mp = vc.query_advice(mp, Rotation::next());
+            let cur_mv = vc.query_advice(mv, Rotation::cur());
+            let next_mv = vc.query_advice(mv, Rotation::next());
+            vec![
+                s_p.clone() * halt.clone() * next_ci,
+                s_p.clone() * halt.clone() * (next_ip - cur_ip),
+                s_p.clone() * halt.clone() * (next_mp - cur_mp),
+                s_p * halt * (next_mv - cur_mv),
+            ]
+        });
+
+        cs.create_gate("P_8: CLEAR keeps the cleared mv_i in acc", |vc| {
+            let ci = vc.query_advice(ci, Rotation::cur());
+            let s_p = vc.query_selector(s_p);
+            let cur_mv = vc.query_advice(mv, Rotation::cur());
+            let cur_acc = vc.query_advice(acc, Rotation::cur());
+            let next_acc = vc.query_advice(acc, Rotation::next());
+            let clear = Expression::Constant(F::from(OPCODES[CLEAR] as u64));
+            // CLEAR: acc_{i+1} = mv_i
+            let expr_clear =
+                create_deselector(ci.clone(), OPCODES[CLEAR]) * (next_acc.clone() - cur_mv);
+            // Otherwise: acc stays the same, so MOVE adds the value of the last CLEAR
+            let expr_others = (ci - clear) * (next_acc - cur_acc);
+            vec![s_p * (expr_clear + expr_others)]
         });
 
         Self {
@@ -207,73 +405,193 @@ impl<const RANGE: usize> Config for ProcessorTableConfig<RANGE> {
             mp,
             mv,
             mvi,
+            acc,
+            prp,
+            input_re,
+            output_re,
             lookup_table,
+            opcode_table,
             s_lookup,
             s_p,
             s_c,
             s_b,
+            s_h,
+            challenges,
         }
     }
 
-    fn load_table(&self, layouter: &mut impl Layouter<Fq>, matrix: &Matrix) -> Result<(), Error> {
-        // Init lookup table
-        self.lookup_table.load_table(layouter, matrix)?;
+    /**
+     * Load the rows of column group `group` into a region of `height` rows and return the
+     * cells of its first and last row, which the main config links to the neighbouring groups,
+     * and the last cells of its running products and running evaluations. Group `i` holds
+     * the rows `i * (height - 1)..(i + 1) * (height - 1) + 1` of the padded trace.
+     * `acc` is the value of acc in the first row of the trace, 0 unless it continues an execution.
+     * With `halt` the last row of the group is constrained to be a halting row (B9), which
+     * the last group of a whole execution sets.
+     */
+    pub fn assign_group<F: FieldExt>(
+        &self,
+        layouter: &mut impl Layouter<F>,
+        matrix: &Matrix,
+        group: usize,
+        height: usize,
+        acc: F,
+        halt: bool,
+    ) -> Result<(GroupCells<F>, ProcessorTerminals<F>), Error> {
+        if group == 0 {
+            // Init lookup table, shared by every group
+            self.lookup_table.load_table(layouter, matrix)?;
+            self.opcode_table.load_table(layouter, matrix)?;
+        }
+        let beta = layouter.get_challenge(self.challenges.beta);
+        let gamma = layouter.get_challenge(self.challenges.gamma);
+        let delta = layouter.get_challenge(self.challenges.delta);
+        let epsilon = layouter.get_challenge(self.challenges.epsilon);
+        let getchar = F::from(OPCODES[GETCHAR] as u64);
+        let putchar = F::from(OPCODES[PUTCHAR] as u64);
+        let clear = F::from(OPCODES[CLEAR] as u64);
         layouter.assign_region(
-            || "Load Processor Table",
+            || region_name("Load Processor Table", group),
             |mut region| {
                 let processor_matrix = &matrix.processor_matrix;
-                // B condition is enabled only for the first row
-                self.s_b.enable(&mut region, 0)?;
-                for (idx, reg) in processor_matrix.iter().enumerate() {
-                    if idx < processor_matrix.len() - 1 {
+                let start = group * (height - 1);
+                let mut prp = Value::known(F::one());
+                let mut input_re = Value::known(F::one());
+                let mut output_re = Value::known(F::one());
+                let mut acc = acc;
+                let mut first = Vec::new();
+                let mut last = Vec::new();
+                let mut terminals = None;
+                if group == 0 {
+                    // B condition is enabled only for the first row
+                    self.s_b.enable(&mut region, 0)?;
+                }
+                // The running values of a group carry on from the rows before it
+                for (idx, reg) in processor_matrix[..start + height].iter().enumerate() {
+                    let acc_cur = acc;
+                    // CLEAR keeps mv for the next rows
+                    if to_field::<F>(reg.current_instruction) == clear {
+                        acc = to_field(reg.memory_value);
+                    }
+                    prp = prp
+                        * (beta
+                            - (Value::known(to_field(reg.cycle))
+                                + gamma * Value::known(to_field(reg.memory_pointer))
+                                + gamma * gamma * Value::known(to_field(reg.memory_value))));
+                    // The byte read by GETCHAR shows up as mv of the next row
+                    if idx > 0 && to_field(processor_matrix[idx - 1].current_instruction) == getchar
+                    {
+                        input_re = input_re * delta + Value::known(to_field(reg.memory_value));
+                    }
+                    // The byte printed by PUTCHAR is mv of the previous row
+                    if idx > 0 && to_field(processor_matrix[idx - 1].current_instruction) == putchar
+                    {
+                        output_re = output_re * epsilon
+                            + Value::known(to_field(processor_matrix[idx - 1].memory_value));
+                    }
+                    if idx < start {
+                        continue;
+                    }
+
+                    let offset = idx - start;
+                    if offset < height - 1 {
                         // P condition is enabled except last row
-                        self.s_p.enable(&mut region, idx)?;
+                        self.s_p.enable(&mut region, offset)?;
+                    } else if halt {
+                        self.s_h.enable(&mut region, offset)?;
                     }
                     // Enable C condition check
-                    self.s_c.enable(&mut region, idx)?;
+                    self.s_c.enable(&mut region, offset)?;
                     // Enable lookup
-                    self.s_lookup.enable(&mut region, idx)?;
+                    self.s_lookup.enable(&mut region, offset)?;
 
-                    region.assign_advice(|| "clk", self.clk, idx, || Value::known(reg.cycle))?;
-                    region.assign_advice(
-                        || "ip",
-                        self.ip,
-                        idx,
-                        || Value::known(reg.instruction_pointer),
-                    )?;
-                    region.assign_advice(
-                        || "ci",
-                        self.ci,
-                        idx,
-                        || Value::known(reg.current_instruction),
-                    )?;
-                    region.assign_advice(
-                        || "ni",
-                        self.ni,
-                        idx,
-                        || Value::known(reg.next_instruction),
-                    )?;
-                    region.assign_advice(
-                        || "mp",
-                        self.mp,
-                        idx,
-                        || Value::known(reg.memory_pointer),
-                    )?;
-                    region.assign_advice(
-                        || "mv",
-                        self.mv,
-                        idx,
-                        || Value::known(reg.memory_value),
-                    )?;
+                    let mut cells = vec![
+                        region.assign_advice(
+                            || "clk",
+                            self.clk,
+                            offset,
+                            || Value::known(to_field(reg.cycle)),
+                        )?,
+                        region.assign_advice(
+                            || "ip",
+                            self.ip,
+                            offset,
+                            || Value::known(to_field(reg.instruction_pointer)),
+                        )?,
+                        region.assign_advice(
+                            || "ci",
+                            self.ci,
+                            offset,
+                            || Value::known(to_field(reg.current_instruction)),
+                        )?,
+                        region.assign_advice(
+                            || "ni",
+                            self.ni,
+                            offset,
+                            || Value::known(to_field(reg.next_instruction)),
+                        )?,
+                        region.assign_advice(
+                            || "mp",
+                            self.mp,
+                            offset,
+                            || Value::known(to_field(reg.memory_pointer)),
+                        )?,
+                        region.assign_advice(
+                            || "mv",
+                            self.mv,
+                            offset,
+                            || Value::known(to_field(reg.memory_value)),
+                        )?,
+                        region.assign_advice(
+                            || "acc",
+                            self.acc,
+                            offset,
+                            || Value::known(acc_cur),
+                        )?,
+                    ];
                     region.assign_advice(
                         || "mvi",
                         self.mvi,
-                        idx,
-                        || Value::known(reg.memory_value_inverse),
+                        offset,
+                        // The interpreter computes the inverse in Fq, recompute it in F
+                        || Value::known(to_field(reg.memory_value).invert().unwrap_or(F::zero())),
                     )?;
+                    let row_terminals = ProcessorTerminals {
+                        prp: region.assign_advice(|| "prp", self.prp, offset, || prp)?,
+                        input_re: region.assign_advice(
+                            || "input_re",
+                            self.input_re,
+                            offset,
+                            || input_re,
+                        )?,
+                        output_re: region.assign_advice(
+                            || "output_re",
+                            self.output_re,
+                            offset,
+                            || output_re,
+                        )?,
+                    };
+                    cells.extend([
+                        row_terminals.prp.clone(),
+                        row_terminals.input_re.clone(),
+                        row_terminals.output_re.clone(),
+                    ]);
+                    if offset == 0 {
+                        first = cells.clone();
+                    }
+                    last = cells;
+                    terminals = Some(row_terminals);
                 }
-                Ok(())
+                let terminals = terminals.ok_or(Error::Synthesis)?;
+                Ok((GroupCells { first, last }, terminals))
             },
         )
     }
 }
+
+/// Last cells of the processor table's cross-table arguments
+pub struct ProcessorTerminals<F: FieldExt> {
+    pub prp: AssignedCell<F, F>,
+    pub input_re: AssignedCell<F, F>,
+    pub output_re: AssignedCell<F, F>,
+}
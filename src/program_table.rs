@@ -0,0 +1,126 @@
+use crate::utils::*;
+use ckb_bf_zkvm::matrix::Matrix;
+
+use halo2_proofs::arithmetic::FieldExt;
+use halo2_proofs::circuit::{Layouter, Value};
+use halo2_proofs::plonk::*;
+use halo2_proofs::poly::Rotation;
+
+/**
+ * Program table, one (ip, ci, ni) row per word of the compiled program.
+ * ci is copied from the program instance column and ni is the ci of the next row, so that the
+ * processor table, which looks up every row in here, is bound to the public program.
+ * For `[` and `]` the next word is the jump target, so jump targets are bound as well.
+ * The table spans every row, past the last word the instance is zero and the rows are (ip, 0, 0),
+ * which are the halting rows of the processor table.
+ */
+#[derive(Clone, Debug, Copy)]
+pub struct ProgramTableConfig {
+    pub(crate) ip: Column<Advice>,
+    pub(crate) ci: Column<Advice>,
+    pub(crate) ni: Column<Advice>,
+    program: Column<Instance>,     // The compiled program, one word per row
+    pub(crate) s_lookup: Selector, // Selector for rows of the program, table side of the lookup
+    s_pg: Selector,                // Selector for condition PG category (Program Table)
+    s_b: Selector,                 // Selector for the first row of the program table
+    s_last: Selector,              // Selector for the last row of the program table
+}
+
+impl<F: FieldExt> Config<F> for ProgramTableConfig {
+    fn configure(cs: &mut ConstraintSystem<F>, _: Challenges) -> Self {
+        let one = Expression::Constant(F::one());
+
+        let ip = cs.advice_column();
+        let ci = cs.advice_column();
+        let ni = cs.advice_column();
+        let program = cs.instance_column();
+        cs.enable_equality(ci);
+        cs.enable_equality(program);
+        let s_lookup = cs.complex_selector();
+        let s_pg = cs.selector();
+        let s_b = cs.selector();
+        let s_last = cs.selector();
+
+        cs.create_gate("PG0: ip_0 = 0", |vc| {
+            let s_b = vc.query_selector(s_b);
+            let ip = vc.query_advice(ip, Rotation::cur());
+            vec![s_b * ip]
+        });
+
+        cs.create_gate("PG1: ip increases by one per row", |vc| {
+            let s_pg = vc.query_selector(s_pg);
+            let cur_ip = vc.query_advice(ip, Rotation::cur());
+            let next_ip = vc.query_advice(ip, Rotation::next());
+            vec![s_pg * (next_ip - cur_ip - one.clone())]
+        });
+
+        cs.create_gate("PG2: ni is the ci of the next row", |vc| {
+            let s_pg = vc.query_selector(s_pg);
+            let cur_ni = vc.query_advice(ni, Rotation::cur());
+            let next_ci = vc.query_advice(ci, Rotation::next());
+            vec![s_pg * (cur_ni - next_ci)]
+        });
+
+        cs.create_gate("PG3: ni_last = 0", |vc| {
+            let s_last = vc.query_selector(s_last);
+            let ni = vc.query_advice(ni, Rotation::cur());
+            vec![s_last * ni]
+        });
+
+        Self {
+            ip,
+            ci,
+            ni,
+            program,
+            s_lookup,
+            s_pg,
+            s_b,
+            s_last,
+        }
+    }
+
+    fn load_table(&self, layouter: &mut impl Layouter<F>, matrix: &Matrix) -> Result<(), Error> {
+        self.assign_table(layouter, matrix, height(matrix, 1))
+    }
+}
+
+impl ProgramTableConfig {
+    /// Load the program table into the first `len` rows.
+    pub fn assign_table<F: FieldExt>(
+        &self,
+        layouter: &mut impl Layouter<F>,
+        matrix: &Matrix,
+        len: usize,
+    ) -> Result<(), Error> {
+        let program = program::<F>(matrix);
+        layouter.assign_region(
+            || "Load Program Table",
+            |mut region| {
+                self.s_b.enable(&mut region, 0)?;
+                self.s_last.enable(&mut region, len - 1)?;
+                for idx in 0..len {
+                    if idx < len - 1 {
+                        // PG condition is enabled except last row
+                        self.s_pg.enable(&mut region, idx)?;
+                    }
+                    // Enable lookup
+                    self.s_lookup.enable(&mut region, idx)?;
+                    region.assign_advice(
+                        || "ip",
+                        self.ip,
+                        idx,
+                        || Value::known(F::from(idx as u64)),
+                    )?;
+                    region.assign_advice_from_instance(|| "ci", self.program, idx, self.ci, idx)?;
+                    region.assign_advice(
+                        || "ni",
+                        self.ni,
+                        idx,
+                        || Value::known(program.get(idx + 1).copied().unwrap_or(F::zero())),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+}
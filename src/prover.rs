@@ -0,0 +1,317 @@
+/// Aggregation of KZG proofs into a single proof, by a circuit that verifies them.
+pub mod aggregation;
+/// Verifier contracts for the EVM, generated from the verifying key.
+pub mod evm;
+/// The same API over the pasta curves with IPA commitments, which need no trusted setup.
+pub mod ipa;
+
+use crate::error::{Error, Result};
+use crate::main_config::MyCircuit;
+use crate::segment::{SegmentCircuit, SegmentProof};
+
+use halo2_proofs::arithmetic::FieldExt;
+use halo2_proofs::dev::MockProver;
+use halo2_proofs::halo2curves::bn256::{Bn256, Fr, G1Affine};
+use halo2_proofs::plonk::{
+    self, create_proof, keygen_pk, keygen_vk, verify_proof, Circuit, ProvingKey, VerifyingKey,
+};
+use halo2_proofs::poly::kzg::commitment::{KZGCommitmentScheme, ParamsKZG};
+use halo2_proofs::poly::kzg::multiopen::{ProverSHPLONK, VerifierSHPLONK};
+use halo2_proofs::poly::kzg::strategy::{AccumulatorStrategy, SingleStrategy};
+use halo2_proofs::poly::VerificationStrategy;
+use halo2_proofs::transcript::{
+    Blake2bRead, Blake2bWrite, Challenge255, EncodedChallenge, Keccak256Read, Keccak256Write,
+    PoseidonRead, PoseidonWrite, TranscriptReadBuffer, TranscriptWriterBuffer,
+};
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Generate the structured reference string for circuits of size 2^k.
+/// The randomness is thrown away, so this is only suitable for testing.
+pub fn setup(k: u32) -> ParamsKZG<Bn256> {
+    ParamsKZG::<Bn256>::setup(k, OsRng)
+}
+
+/// Generate the proving key, the verifying key is available through `pk.get_vk()`.
+/// `circuit` is a `MyCircuit` or a `SegmentCircuit`.
+pub fn keygen<C: Circuit<Fr>>(
+    params: &ParamsKZG<Bn256>,
+    circuit: &C,
+) -> Result<ProvingKey<G1Affine>> {
+    let vk = keygen_vk(params, circuit).map_err(Error::Synthesis)?;
+    keygen_pk(params, vk, circuit).map_err(Error::Synthesis)
+}
+
+/**
+ * Hash function of the Fiat-Shamir transcript. Blake2b is the fastest one natively,
+ * Keccak256 is what an EVM verifier can afford, and Poseidon is what a circuit verifying
+ * the proof can afford. A proof only verifies with the transcript it was created with.
+ * The verifiers of `evm` and `aggregation` read their own transcripts, see their `prove`.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscriptKind {
+    #[default]
+    Blake2b,
+    Keccak256,
+    Poseidon,
+}
+
+impl TranscriptKind {
+    /// Tag of the transcript in the binary encoding of a proof bundle.
+    pub fn tag(self) -> u8 {
+        match self {
+            Self::Blake2b => 0,
+            Self::Keccak256 => 1,
+            Self::Poseidon => 2,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Self::Blake2b),
+            1 => Some(Self::Keccak256),
+            2 => Some(Self::Poseidon),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TranscriptKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Blake2b => write!(f, "blake2b"),
+            Self::Keccak256 => write!(f, "keccak256"),
+            Self::Poseidon => write!(f, "poseidon"),
+        }
+    }
+}
+
+impl FromStr for TranscriptKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "blake2b" => Ok(Self::Blake2b),
+            "keccak256" => Ok(Self::Keccak256),
+            "poseidon" => Ok(Self::Poseidon),
+            _ => Err(format!(
+                "unknown transcript {}, expected blake2b, keccak256 or poseidon",
+                s
+            )),
+        }
+    }
+}
+
+/// Prove the execution in `circuit`, the public instances are `circuit.instances()`.
+pub fn prove<const RANGE: usize, const GROUPS: usize>(
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: MyCircuit<Fr, RANGE, GROUPS>,
+) -> Result<Vec<u8>> {
+    prove_with(params, pk, circuit, TranscriptKind::Blake2b)
+}
+
+/// Prove the execution in `circuit` with the given transcript.
+pub fn prove_with<const RANGE: usize, const GROUPS: usize>(
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: MyCircuit<Fr, RANGE, GROUPS>,
+    transcript: TranscriptKind,
+) -> Result<Vec<u8>> {
+    let instances = circuit.instances();
+    match transcript {
+        TranscriptKind::Blake2b => {
+            create::<_, Challenge255<_>, Blake2bWrite<_, _, _>>(params, pk, circuit, &instances)
+        }
+        TranscriptKind::Keccak256 => {
+            create::<_, Challenge255<_>, Keccak256Write<_, _, _>>(params, pk, circuit, &instances)
+        }
+        TranscriptKind::Poseidon => {
+            create::<_, Challenge255<_>, PoseidonWrite<_, _, _>>(params, pk, circuit, &instances)
+        }
+    }
+}
+
+/// Prove a segment of an execution with the given transcript and return the proof with the
+/// public data of the segment, which `segment::verify_chain` checks against the other segments.
+pub fn prove_segment<const RANGE: usize>(
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: SegmentCircuit<Fr, RANGE>,
+    transcript: TranscriptKind,
+) -> Result<SegmentProof> {
+    let instances = circuit.instances();
+    let mut segment = SegmentProof::new(&circuit, transcript, Vec::new());
+    segment.proof = match transcript {
+        TranscriptKind::Blake2b => {
+            create::<_, Challenge255<_>, Blake2bWrite<_, _, _>>(params, pk, circuit, &instances)
+        }
+        TranscriptKind::Keccak256 => {
+            create::<_, Challenge255<_>, Keccak256Write<_, _, _>>(params, pk, circuit, &instances)
+        }
+        TranscriptKind::Poseidon => {
+            create::<_, Challenge255<_>, PoseidonWrite<_, _, _>>(params, pk, circuit, &instances)
+        }
+    }?;
+    Ok(segment)
+}
+
+/// Create a proof with the transcript `T`, whose challenges are encoded as `E`: halo2's
+/// transcripts use `Challenge255`, those of snark-verifier their own encoding.
+fn create<C, E, T>(
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: C,
+    instances: &[Vec<Fr>],
+) -> Result<Vec<u8>>
+where
+    C: Circuit<Fr>,
+    E: EncodedChallenge<G1Affine>,
+    T: TranscriptWriterBuffer<Vec<u8>, G1Affine, E>,
+{
+    let instances = instances.iter().map(|v| v.as_slice()).collect::<Vec<_>>();
+    let mut transcript = T::init(vec![]);
+    create_proof::<KZGCommitmentScheme<Bn256>, ProverSHPLONK<'_, Bn256>, E, _, T, _>(
+        params,
+        pk,
+        &[circuit],
+        &[instances.as_slice()],
+        OsRng,
+        &mut transcript,
+    )
+    .map_err(Error::Synthesis)?;
+    Ok(transcript.finalize())
+}
+
+/// Verify `proof` against the public instances (program, input, output).
+pub fn verify(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proof: &[u8],
+    instances: &[Vec<Fr>],
+) -> Result<()> {
+    verify_with(params, vk, proof, instances, TranscriptKind::Blake2b)
+}
+
+/// Verify `proof` against the public instances, `transcript` is the one it was created with.
+pub fn verify_with(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proof: &[u8],
+    instances: &[Vec<Fr>],
+    transcript: TranscriptKind,
+) -> Result<()> {
+    match transcript {
+        TranscriptKind::Blake2b => check::<Blake2bRead<_, _, _>>(params, vk, proof, instances),
+        TranscriptKind::Keccak256 => check::<Keccak256Read<_, _, _>>(params, vk, proof, instances),
+        TranscriptKind::Poseidon => check::<PoseidonRead<_, _, _>>(params, vk, proof, instances),
+    }
+}
+
+fn check<'a, T>(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proof: &'a [u8],
+    instances: &[Vec<Fr>],
+) -> Result<()>
+where
+    T: TranscriptReadBuffer<&'a [u8], G1Affine, Challenge255<G1Affine>>,
+{
+    let instances = instances.iter().map(|v| v.as_slice()).collect::<Vec<_>>();
+    let strategy = SingleStrategy::new(params);
+    let mut transcript = T::init(proof);
+    verify_proof::<
+        KZGCommitmentScheme<Bn256>,
+        VerifierSHPLONK<'_, Bn256>,
+        Challenge255<G1Affine>,
+        T,
+        SingleStrategy<'_, Bn256>,
+    >(
+        params,
+        vk,
+        strategy,
+        &[instances.as_slice()],
+        &mut transcript,
+    )
+    .map_err(Error::Verification)
+}
+
+/**
+ * Verify several proofs for circuits of the same size, e.g. proofs of different programs.
+ * The verifying key only depends on k, so every proof is checked against `vk`.
+ *
+ * This is native batch verification, not aggregation: every proof is still read and checked
+ * by the verifier, only their pairing checks are accumulated into a single one, each proof
+ * scaled by a random factor, so n proofs cost one pairing check instead of n. Every proof
+ * must have been created with `transcript`.
+ */
+pub fn verify_accumulated(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proofs: &[Vec<u8>],
+    instances: &[Vec<Vec<Fr>>],
+    transcript: TranscriptKind,
+) -> Result<()> {
+    if proofs.len() != instances.len() {
+        return Err(Error::Verification(plonk::Error::InvalidInstances));
+    }
+    match transcript {
+        TranscriptKind::Blake2b => {
+            accumulate::<Blake2bRead<_, _, _>>(params, vk, proofs, instances)
+        }
+        TranscriptKind::Keccak256 => {
+            accumulate::<Keccak256Read<_, _, _>>(params, vk, proofs, instances)
+        }
+        TranscriptKind::Poseidon => {
+            accumulate::<PoseidonRead<_, _, _>>(params, vk, proofs, instances)
+        }
+    }
+}
+
+fn accumulate<'a, T>(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proofs: &'a [Vec<u8>],
+    instances: &[Vec<Vec<Fr>>],
+) -> Result<()>
+where
+    T: TranscriptReadBuffer<&'a [u8], G1Affine, Challenge255<G1Affine>>,
+{
+    let mut strategy = AccumulatorStrategy::new(params);
+    for (proof, instances) in proofs.iter().zip(instances) {
+        let instances = instances.iter().map(|v| v.as_slice()).collect::<Vec<_>>();
+        let mut transcript = T::init(proof.as_slice());
+        strategy = verify_proof::<
+            KZGCommitmentScheme<Bn256>,
+            VerifierSHPLONK<'_, Bn256>,
+            Challenge255<G1Affine>,
+            T,
+            AccumulatorStrategy<'_, Bn256>,
+        >(
+            params,
+            vk,
+            strategy,
+            &[instances.as_slice()],
+            &mut transcript,
+        )
+        .map_err(Error::Verification)?;
+    }
+    // The accumulated pairing check
+    if strategy.finalize() {
+        Ok(())
+    } else {
+        Err(Error::Verification(plonk::Error::ConstraintSystemFailure))
+    }
+}
+
+/// Check the constraints with the MockProver, without generating keys or a proof.
+pub fn mock<F: FieldExt, const RANGE: usize, const GROUPS: usize>(
+    circuit: &MyCircuit<F, RANGE, GROUPS>,
+) -> Result<()> {
+    let prover =
+        MockProver::run(circuit.k(), circuit, circuit.instances()).map_err(Error::Synthesis)?;
+    prover.verify().map_err(Error::Unsatisfied)
+}
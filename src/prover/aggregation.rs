@@ -0,0 +1,340 @@
+use crate::error::{Error, Result};
+use crate::main_config::MyCircuit;
+use crate::prover::create;
+
+use halo2_proofs::arithmetic::CurveAffine;
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner, Value};
+use halo2_proofs::halo2curves::bn256::{Bn256, Fq, Fr, G1Affine};
+use halo2_proofs::halo2curves::pairing::Engine;
+use halo2_proofs::plonk::{self, Circuit, ConstraintSystem, ProvingKey, VerifyingKey};
+use halo2_proofs::poly::commitment::Params;
+use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+use halo2_proofs::transcript::{Blake2bWrite, Challenge255};
+use rand_core::OsRng;
+use snark_verifier::loader;
+use snark_verifier::loader::halo2::halo2_wrong_ecc::integer::rns::Rns;
+use snark_verifier::loader::halo2::halo2_wrong_ecc::maingate::{
+    AssignedValue, MainGate, MainGateConfig, MainGateInstructions, RangeChip, RangeConfig,
+    RangeInstructions, RegionCtx,
+};
+use snark_verifier::loader::halo2::halo2_wrong_ecc::{self, EccConfig};
+use snark_verifier::loader::native::NativeLoader;
+use snark_verifier::pcs::kzg::{
+    Bdfg21, KzgAccumulator, KzgAs, KzgSuccinctVerifyingKey, LimbsEncoding,
+};
+use snark_verifier::pcs::{AccumulationScheme, AccumulationSchemeProver};
+use snark_verifier::system::halo2::transcript::halo2::PoseidonTranscript;
+use snark_verifier::system::halo2::{compile, Config};
+use snark_verifier::util::arithmetic::{fe_from_limbs, fe_to_limbs};
+use snark_verifier::verifier::{self, PlonkVerifier};
+use snark_verifier::Protocol;
+use std::rc::Rc;
+
+/// Number of limbs and bits per limb of a base field coordinate of the accumulator in the
+/// instances of the aggregation circuit.
+const LIMBS: usize = 4;
+const BITS: usize = 68;
+
+// SHPLONK, the multi-open scheme of `prover`
+type As = KzgAs<Bn256, Bdfg21>;
+type Plonk = verifier::Plonk<As, LimbsEncoding<LIMBS, BITS>>;
+type Svk = KzgSuccinctVerifyingKey<G1Affine>;
+type BaseFieldEccChip = halo2_wrong_ecc::BaseFieldEccChip<G1Affine, LIMBS, BITS>;
+type Halo2Loader<'a> = loader::halo2::Halo2Loader<'a, G1Affine, BaseFieldEccChip>;
+/// Poseidon transcript of the aggregated proofs, cheap to hash in the aggregation circuit.
+type Transcript<L, S> = PoseidonTranscript<G1Affine, L, S, 5, 4, 8, 60>;
+
+/// A proof to aggregate with its public instances, see `prove`.
+#[derive(Clone, Debug)]
+pub struct Snark {
+    protocol: Protocol<G1Affine>,
+    pub instances: Vec<Vec<Fr>>,
+    pub proof: Vec<u8>,
+}
+
+/// The params of the proofs to aggregate: `params` of the aggregation circuit downsized to 2^k
+/// rows. The accumulator is checked against the SRS of the aggregation circuit, so the proofs
+/// must be created with the same one.
+pub fn downsize(params: &ParamsKZG<Bn256>, k: u32) -> ParamsKZG<Bn256> {
+    let mut params = params.clone();
+    params.downsize(k);
+    params
+}
+
+/// Prove the execution in `circuit` with the transcript the aggregation circuit reads,
+/// `params` come from `downsize`.
+pub fn prove<const RANGE: usize, const GROUPS: usize>(
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: MyCircuit<Fr, RANGE, GROUPS>,
+) -> Result<Snark> {
+    let instances = circuit.instances();
+    let num_instance = instances.iter().map(Vec::len).collect();
+    let protocol = compile(
+        params,
+        pk.get_vk(),
+        Config::kzg().with_num_instance(num_instance),
+    );
+    let proof = create::<_, _, Transcript<NativeLoader, _>>(params, pk, circuit, &instances)?;
+    Ok(Snark {
+        protocol,
+        instances,
+        proof,
+    })
+}
+
+fn aggregation_error(err: snark_verifier::Error) -> Error {
+    Error::Aggregation(format!("{:?}", err))
+}
+
+/// A `Snark` as a witness of the aggregation circuit, unknown during key generation.
+#[derive(Clone)]
+struct SnarkWitness {
+    protocol: Protocol<G1Affine>,
+    instances: Vec<Vec<Value<Fr>>>,
+    proof: Value<Vec<u8>>,
+}
+
+impl SnarkWitness {
+    fn without_witnesses(&self) -> Self {
+        Self {
+            protocol: self.protocol.clone(),
+            instances: self
+                .instances
+                .iter()
+                .map(|column| vec![Value::unknown(); column.len()])
+                .collect(),
+            proof: Value::unknown(),
+        }
+    }
+}
+
+impl From<Snark> for SnarkWitness {
+    fn from(snark: Snark) -> Self {
+        Self {
+            protocol: snark.protocol,
+            instances: snark
+                .instances
+                .into_iter()
+                .map(|column| column.into_iter().map(Value::known).collect())
+                .collect(),
+            proof: Value::known(snark.proof),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AggregationConfig {
+    main_gate: MainGateConfig,
+    range: RangeConfig,
+}
+
+/**
+ * Circuit verifying the proofs of several executions. Each proof is reduced to a KZG
+ * accumulator, a pair of points that passes a pairing check iff the proof is valid, and the
+ * accumulators are folded into one, whose pairing check is left to the verifier of the
+ * aggregated proof, see `decide`.
+ *
+ * The instance holds the accumulator, 4 * LIMBS limbs of its coordinates, followed by the
+ * instances of the proofs in order, each flattened column by column.
+ */
+#[derive(Clone)]
+pub struct AggregationCircuit {
+    svk: Svk,
+    snarks: Vec<SnarkWitness>,
+    instances: Vec<Fr>,
+    as_proof: Value<Vec<u8>>,
+}
+
+impl AggregationCircuit {
+    /// Aggregate `snarks`, proven with `downsize(params, k)` for any k.
+    pub fn new(params: &ParamsKZG<Bn256>, snarks: Vec<Snark>) -> Result<Self> {
+        let svk: Svk = params.get_g()[0].into();
+        let mut accumulators = Vec::new();
+        for snark in &snarks {
+            let mut transcript = Transcript::<NativeLoader, _>::new(snark.proof.as_slice());
+            let proof = Plonk::read_proof(&svk, &snark.protocol, &snark.instances, &mut transcript)
+                .map_err(aggregation_error)?;
+            accumulators.extend(
+                Plonk::succinct_verify(&svk, &snark.protocol, &snark.instances, &proof)
+                    .map_err(aggregation_error)?,
+            );
+        }
+        let mut transcript = Transcript::<NativeLoader, _>::new(Vec::new());
+        let KzgAccumulator { lhs, rhs } =
+            As::create_proof(&Default::default(), &accumulators, &mut transcript, OsRng)
+                .map_err(aggregation_error)?;
+        let as_proof = transcript.finalize();
+
+        let mut instances = [lhs.x, lhs.y, rhs.x, rhs.y]
+            .map(fe_to_limbs::<_, _, LIMBS, BITS>)
+            .concat();
+        instances.extend(
+            snarks
+                .iter()
+                .flat_map(|snark| snark.instances.iter().flatten().copied()),
+        );
+        Ok(Self {
+            svk,
+            snarks: snarks.into_iter().map(SnarkWitness::from).collect(),
+            instances,
+            as_proof: Value::known(as_proof),
+        })
+    }
+
+    /// The single instance column: the accumulator, then the instances of the proofs.
+    pub fn instances(&self) -> Vec<Vec<Fr>> {
+        vec![self.instances.clone()]
+    }
+
+    /// Verify the proofs in the circuit and return the cells of the instance: the limbs of the
+    /// accumulator, then the instances of the proofs.
+    fn aggregate(
+        &self,
+        loader: &Rc<Halo2Loader<'_>>,
+    ) -> std::result::Result<Vec<AssignedValue<Fr>>, snark_verifier::Error> {
+        let mut accumulators = Vec::new();
+        let mut instances = Vec::new();
+        for snark in &self.snarks {
+            let protocol = snark.protocol.loaded(loader);
+            let assigned = snark
+                .instances
+                .iter()
+                .map(|column| {
+                    column
+                        .iter()
+                        .map(|value| loader.assign_scalar(*value))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            let proof = snark.proof.as_ref().map(Vec::as_slice);
+            let mut transcript = Transcript::<Rc<Halo2Loader>, _>::new(loader, proof);
+            let proof = Plonk::read_proof(&self.svk, &protocol, &assigned, &mut transcript)?;
+            accumulators.extend(Plonk::succinct_verify(
+                &self.svk, &protocol, &assigned, &proof,
+            )?);
+            instances.extend(assigned.into_iter().flatten());
+        }
+        let as_proof = self.as_proof.as_ref().map(Vec::as_slice);
+        let mut transcript = Transcript::<Rc<Halo2Loader>, _>::new(loader, as_proof);
+        let proof = As::read_proof(&Default::default(), &accumulators, &mut transcript)?;
+        let KzgAccumulator { lhs, rhs } = As::verify(&Default::default(), &accumulators, &proof)?;
+
+        let (lhs, rhs) = (lhs.assigned(), rhs.assigned());
+        let limbs = [lhs.x(), lhs.y(), rhs.x(), rhs.y()]
+            .into_iter()
+            .flat_map(|coordinate| coordinate.limbs())
+            .map(|limb| limb.into());
+        Ok(limbs
+            .chain(
+                instances
+                    .into_iter()
+                    .map(|instance| instance.into_assigned()),
+            )
+            .collect())
+    }
+}
+
+impl Circuit<Fr> for AggregationCircuit {
+    type Config = AggregationConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            svk: self.svk,
+            snarks: self
+                .snarks
+                .iter()
+                .map(SnarkWitness::without_witnesses)
+                .collect(),
+            instances: Vec::new(),
+            as_proof: Value::unknown(),
+        }
+    }
+
+    fn configure(cs: &mut ConstraintSystem<Fr>) -> Self::Config {
+        let main_gate = MainGate::<Fr>::configure(cs);
+        let range = RangeChip::<Fr>::configure(
+            cs,
+            &main_gate,
+            vec![BITS / LIMBS],
+            Rns::<Fq, Fr, LIMBS, BITS>::construct().overflow_lengths(),
+        );
+        AggregationConfig { main_gate, range }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fr>,
+    ) -> std::result::Result<(), plonk::Error> {
+        let main_gate = MainGate::<Fr>::new(config.main_gate.clone());
+        RangeChip::<Fr>::new(config.range.clone()).load_table(&mut layouter)?;
+
+        let cells = layouter.assign_region(
+            || "Aggregate",
+            |region| {
+                let ecc_chip = BaseFieldEccChip::new(EccConfig::new(
+                    config.range.clone(),
+                    config.main_gate.clone(),
+                ));
+                let loader = Halo2Loader::new(ecc_chip, RegionCtx::new(region, 0));
+                self.aggregate(&loader).map_err(|_| plonk::Error::Synthesis)
+            },
+        )?;
+        // The instances of the proofs are copied from the instance column, so the aggregated
+        // proof is a proof of their statements
+        for (row, cell) in cells.into_iter().enumerate() {
+            main_gate.expose_public(layouter.namespace(|| "Expose"), cell, row)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prove the aggregation of the proofs in `circuit`, the public instances are
+/// `circuit.instances()`.
+pub fn prove_aggregation(
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: AggregationCircuit,
+) -> Result<Vec<u8>> {
+    let instances = circuit.instances();
+    create::<_, Challenge255<_>, Blake2bWrite<_, _, _>>(params, pk, circuit, &instances)
+}
+
+/// Verify an aggregated proof against the public instances of its `AggregationCircuit`:
+/// the proof itself, then the pairing check of its accumulator, see `decide`.
+pub fn verify(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proof: &[u8],
+    instances: &[Vec<Fr>],
+) -> Result<()> {
+    super::verify(params, vk, proof, instances)?;
+    decide(params, instances)
+}
+
+/// Check the accumulator at the start of the instances of an `AggregationCircuit`: it passes
+/// the pairing check iff every aggregated proof is valid.
+pub fn decide(params: &ParamsKZG<Bn256>, instances: &[Vec<Fr>]) -> Result<()> {
+    let limbs = instances
+        .first()
+        .filter(|column| column.len() >= 4 * LIMBS)
+        .ok_or(Error::Verification(plonk::Error::InvalidInstances))?;
+    let [lhs_x, lhs_y, rhs_x, rhs_y] = [0, 1, 2, 3].map(|coordinate| {
+        fe_from_limbs::<_, Fq, LIMBS, BITS>(std::array::from_fn(|limb| {
+            limbs[coordinate * LIMBS + limb]
+        }))
+    });
+    let point = |x, y| Option::<G1Affine>::from(G1Affine::from_xy(x, y));
+    let (lhs, rhs) = match (point(lhs_x, lhs_y), point(rhs_x, rhs_y)) {
+        (Some(lhs), Some(rhs)) => (lhs, rhs),
+        _ => return Err(Error::Verification(plonk::Error::InvalidInstances)),
+    };
+    // e(lhs, g2) = e(rhs, s * g2)
+    if Bn256::pairing(&lhs, &params.g2()) == Bn256::pairing(&rhs, &params.s_g2()) {
+        Ok(())
+    } else {
+        Err(Error::Verification(plonk::Error::ConstraintSystemFailure))
+    }
+}
@@ -0,0 +1,90 @@
+use crate::error::{Error, Result};
+use crate::main_config::MyCircuit;
+use crate::prover::create;
+
+use halo2_proofs::halo2curves::bn256::{Bn256, Fq, Fr, G1Affine};
+use halo2_proofs::plonk::{self, ProvingKey, VerifyingKey};
+use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+use snark_verifier::loader::evm::{self, encode_calldata, Address, EvmLoader, ExecutorBuilder};
+use snark_verifier::loader::native::NativeLoader;
+use snark_verifier::pcs::kzg::{Bdfg21, KzgAs, KzgDecidingKey, KzgSuccinctVerifyingKey};
+use snark_verifier::system::halo2::transcript::evm::EvmTranscript;
+use snark_verifier::system::halo2::{compile, Config};
+use snark_verifier::verifier::{self, PlonkVerifier};
+use std::process::Command;
+use std::rc::Rc;
+
+// SHPLONK, the multi-open scheme of `prover`
+type Plonk = verifier::Plonk<KzgAs<Bn256, Bdfg21>>;
+
+fn evm_error(err: snark_verifier::Error) -> Error {
+    Error::Evm(format!("{:?}", err))
+}
+
+/**
+ * Yul source of a contract verifying the proofs of `evm::prove` for the circuit of `vk`.
+ * The contract is specific to the number of public instances in each column, `num_instance`,
+ * which is `circuit.instances().iter().map(Vec::len)` for a `MyCircuit`: the length of the
+ * program and the number of input and output bytes.
+ */
+pub fn verifier_yul(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+) -> Result<String> {
+    let protocol = compile(
+        params,
+        vk,
+        Config::kzg().with_num_instance(num_instance.clone()),
+    );
+    let svk: KzgSuccinctVerifyingKey<G1Affine> = params.get_g()[0].into();
+    let dk: KzgDecidingKey<Bn256> = (params.g2(), params.s_g2()).into();
+
+    // Verifying with the EVM loader writes the verifier instead of running it
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let protocol = protocol.loaded(&loader);
+    let mut transcript = EvmTranscript::<_, Rc<EvmLoader>, _, _>::new(&loader);
+    let instances = transcript.load_instances(num_instance);
+    let proof =
+        Plonk::read_proof(&svk, &protocol, &instances, &mut transcript).map_err(evm_error)?;
+    Plonk::verify(&svk, &dk, &protocol, &instances, &proof).map_err(evm_error)?;
+    Ok(loader.yul_code())
+}
+
+/// Deployment bytecode of the verifier, see `verifier_yul`. Compiling Yul needs `solc` on the PATH.
+pub fn verifier_bytecode(yul: &str) -> Result<Vec<u8>> {
+    if Command::new("solc").arg("--version").output().is_err() {
+        return Err(Error::Evm("solc is not installed".to_string()));
+    }
+    Ok(evm::compile_yul(yul))
+}
+
+/// Prove the execution in `circuit` with the Keccak256 transcript of the EVM verifier, which
+/// is not the one of `TranscriptKind::Keccak256`.
+pub fn prove<const RANGE: usize, const GROUPS: usize>(
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: MyCircuit<Fr, RANGE, GROUPS>,
+) -> Result<Vec<u8>> {
+    let instances = circuit.instances();
+    create::<_, _, EvmTranscript<_, NativeLoader, _, _>>(params, pk, circuit, &instances)
+}
+
+/// Deploy the verifier `bytecode` in an in-memory EVM and call it with the public instances
+/// and `proof`, the call reverts unless the proof verifies.
+pub fn verify(bytecode: Vec<u8>, instances: &[Vec<Fr>], proof: &[u8]) -> Result<()> {
+    let mut evm = ExecutorBuilder::default()
+        .with_gas_limit(u64::MAX.into())
+        .build();
+    let caller = Address::from_low_u64_be(0xfe);
+    let verifier = evm
+        .deploy(caller, bytecode.into(), 0.into())
+        .address
+        .ok_or_else(|| Error::Evm("the verifier can't be deployed".to_string()))?;
+    let calldata = encode_calldata(instances, proof);
+    let result = evm.call_raw(caller, verifier, calldata.into(), 0.into());
+    if result.reverted {
+        return Err(Error::Verification(plonk::Error::ConstraintSystemFailure));
+    }
+    Ok(())
+}
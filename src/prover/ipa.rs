@@ -0,0 +1,145 @@
+use crate::error::{Error, Result};
+use crate::main_config::MyCircuit;
+use crate::prover::TranscriptKind;
+
+use halo2_proofs::halo2curves::pasta::{EqAffine, Fp};
+use halo2_proofs::plonk::{
+    create_proof, keygen_pk, keygen_vk, verify_proof, ProvingKey, VerifyingKey,
+};
+use halo2_proofs::poly::commitment::ParamsProver;
+use halo2_proofs::poly::ipa::commitment::{IPACommitmentScheme, ParamsIPA};
+use halo2_proofs::poly::ipa::multiopen::{ProverIPA, VerifierIPA};
+use halo2_proofs::poly::ipa::strategy::SingleStrategy;
+use halo2_proofs::poly::VerificationStrategy;
+use halo2_proofs::transcript::{
+    Blake2bRead, Blake2bWrite, Challenge255, Keccak256Read, Keccak256Write, PoseidonRead,
+    PoseidonWrite, TranscriptReadBuffer, TranscriptWriterBuffer,
+};
+use rand_core::OsRng;
+
+/// Generate the params for circuits of size 2^k. The setup is transparent: the params are
+/// derived from k alone, so anyone can regenerate and check them.
+pub fn setup(k: u32) -> ParamsIPA<EqAffine> {
+    ParamsIPA::<EqAffine>::new(k)
+}
+
+/// Generate the proving key, the verifying key is available through `pk.get_vk()`.
+pub fn keygen<const RANGE: usize, const GROUPS: usize>(
+    params: &ParamsIPA<EqAffine>,
+    circuit: &MyCircuit<Fp, RANGE, GROUPS>,
+) -> Result<ProvingKey<EqAffine>> {
+    let vk = keygen_vk(params, circuit).map_err(Error::Synthesis)?;
+    keygen_pk(params, vk, circuit).map_err(Error::Synthesis)
+}
+
+/// Prove the execution in `circuit`, the public instances are `circuit.instances()`.
+pub fn prove<const RANGE: usize, const GROUPS: usize>(
+    params: &ParamsIPA<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    circuit: MyCircuit<Fp, RANGE, GROUPS>,
+) -> Result<Vec<u8>> {
+    prove_with(params, pk, circuit, TranscriptKind::Blake2b)
+}
+
+/// Prove the execution in `circuit` with the given transcript.
+pub fn prove_with<const RANGE: usize, const GROUPS: usize>(
+    params: &ParamsIPA<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    circuit: MyCircuit<Fp, RANGE, GROUPS>,
+    transcript: TranscriptKind,
+) -> Result<Vec<u8>> {
+    match transcript {
+        TranscriptKind::Blake2b => {
+            create::<Blake2bWrite<_, _, _>, RANGE, GROUPS>(params, pk, circuit)
+        }
+        TranscriptKind::Keccak256 => {
+            create::<Keccak256Write<_, _, _>, RANGE, GROUPS>(params, pk, circuit)
+        }
+        TranscriptKind::Poseidon => {
+            create::<PoseidonWrite<_, _, _>, RANGE, GROUPS>(params, pk, circuit)
+        }
+    }
+}
+
+fn create<T, const RANGE: usize, const GROUPS: usize>(
+    params: &ParamsIPA<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    circuit: MyCircuit<Fp, RANGE, GROUPS>,
+) -> Result<Vec<u8>>
+where
+    T: TranscriptWriterBuffer<Vec<u8>, EqAffine, Challenge255<EqAffine>>,
+{
+    let instances = circuit.instances();
+    let instances = instances.iter().map(|v| v.as_slice()).collect::<Vec<_>>();
+    let mut transcript = T::init(vec![]);
+    create_proof::<
+        IPACommitmentScheme<EqAffine>,
+        ProverIPA<'_, EqAffine>,
+        Challenge255<EqAffine>,
+        _,
+        T,
+        _,
+    >(
+        params,
+        pk,
+        &[circuit],
+        &[instances.as_slice()],
+        OsRng,
+        &mut transcript,
+    )
+    .map_err(Error::Synthesis)?;
+    Ok(transcript.finalize())
+}
+
+/// Verify `proof` against the public instances (program, input, output).
+pub fn verify(
+    params: &ParamsIPA<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    proof: &[u8],
+    instances: &[Vec<Fp>],
+) -> Result<()> {
+    verify_with(params, vk, proof, instances, TranscriptKind::Blake2b)
+}
+
+/// Verify `proof` against the public instances, `transcript` is the one it was created with.
+pub fn verify_with(
+    params: &ParamsIPA<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    proof: &[u8],
+    instances: &[Vec<Fp>],
+    transcript: TranscriptKind,
+) -> Result<()> {
+    match transcript {
+        TranscriptKind::Blake2b => check::<Blake2bRead<_, _, _>>(params, vk, proof, instances),
+        TranscriptKind::Keccak256 => check::<Keccak256Read<_, _, _>>(params, vk, proof, instances),
+        TranscriptKind::Poseidon => check::<PoseidonRead<_, _, _>>(params, vk, proof, instances),
+    }
+}
+
+fn check<'a, T>(
+    params: &ParamsIPA<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    proof: &'a [u8],
+    instances: &[Vec<Fp>],
+) -> Result<()>
+where
+    T: TranscriptReadBuffer<&'a [u8], EqAffine, Challenge255<EqAffine>>,
+{
+    let instances = instances.iter().map(|v| v.as_slice()).collect::<Vec<_>>();
+    let strategy = SingleStrategy::new(params);
+    let mut transcript = T::init(proof);
+    verify_proof::<
+        IPACommitmentScheme<EqAffine>,
+        VerifierIPA<'_, EqAffine>,
+        Challenge255<EqAffine>,
+        T,
+        SingleStrategy<'_, EqAffine>,
+    >(
+        params,
+        vk,
+        strategy,
+        &[instances.as_slice()],
+        &mut transcript,
+    )
+    .map_err(Error::Verification)
+}
@@ -1,8 +1,8 @@
 use crate::utils::*;
 use ckb_bf_zkvm::matrix::Matrix;
 
+use halo2_proofs::arithmetic::FieldExt;
 use halo2_proofs::circuit::{Layouter, Value};
-use halo2_proofs::halo2curves::bn256::Fq;
 use halo2_proofs::plonk::*;
 
 #[derive(Clone, Debug, Copy)]
@@ -10,13 +10,13 @@ pub struct RangeTableConfig<const RANGE: usize> {
     pub table: TableColumn,
 }
 
-impl<const RANGE: usize> Config for RangeTableConfig<RANGE> {
-    fn configure(cs: &mut ConstraintSystem<Fq>) -> Self {
+impl<F: FieldExt, const RANGE: usize> Config<F> for RangeTableConfig<RANGE> {
+    fn configure(cs: &mut ConstraintSystem<F>, _: Challenges) -> Self {
         let table = cs.lookup_table_column();
         Self { table }
     }
 
-    fn load_table(&self, layouter: &mut impl Layouter<Fq>, _: &Matrix) -> Result<(), Error> {
+    fn load_table(&self, layouter: &mut impl Layouter<F>, _: &Matrix) -> Result<(), Error> {
         layouter.assign_table(
             || "load range-check table",
             |mut table| {
@@ -26,7 +26,7 @@ impl<const RANGE: usize> Config for RangeTableConfig<RANGE> {
                         || "value",
                         self.table,
                         offset,
-                        || Value::known(Fq::from(value as u64)),
+                        || Value::known(F::from(value as u64)),
                     )?;
                     offset += 1;
                 }
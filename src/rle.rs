@@ -0,0 +1,345 @@
+use crate::error::{Error, Result};
+use crate::main_config::check_trace;
+use crate::utils::*;
+use ckb_bf_zkvm::matrix::{InstructionMatrixRow, Matrix, MemoryMatrixRow, Register};
+
+use halo2_proofs::arithmetic::Field;
+use halo2_proofs::halo2curves::bn256::Fq;
+use std::collections::BTreeMap;
+
+/// Longest run folded into one instruction. The gates of ADD, SUB, SHR and SHL are only
+/// sound for arguments below RANGE, and the memory table range-checks pointer jumps.
+pub const MAX_RUN: usize = 255;
+
+/// A word of the folded program, jump targets are resolved once every instruction is placed.
+enum Word {
+    Op(u8),
+    Arg(u64),
+    Target(usize), // Input ip the bracket jumps to
+}
+
+/// Where the words of a `code::compile` program end up once it is folded.
+pub(crate) struct Layout {
+    pub words: Vec<u64>,     // The folded program
+    pub origins: Vec<usize>, // Input ip of the instruction holding each folded word
+}
+
+/**
+ * Fold a `code::compile` program:
+ *
+ * - runs of ADD, SUB, SHR and SHL become one instruction followed by its argument, the
+ *   length of the run, at most `MAX_RUN`. An argument is an extra word, like the jump
+ *   target of a bracket, so the processor table reads it from ni.
+ * - clear loops (`[-]`, `[+]`) become CLEAR, which also keeps the cleared value in acc.
+ * - move loops (`[->+<]`, `[>>+<<-]`, `[->+>+<<]`...) become LB, which jumps past the macro
+ *   on a cell holding 0 like the loop does, then CLEAR, a shift to each target followed by
+ *   MOVE, which adds acc to the cell, and a shift back.
+ */
+pub(crate) fn layout(program: &[u64]) -> Layout {
+    let foldable = [SHL, SHR, ADD, SUB].map(|op| OPCODES[op] as u64);
+    // Folded ip of the instruction holding each word of the input
+    let mut ips = Vec::with_capacity(program.len() + 1);
+    let mut instructions = Vec::new(); // Input ip and folded words of every instruction
+    let word = |ip: usize| program.get(ip).copied().unwrap_or(0);
+    let mut ip = 0;
+    let mut folded_ip = 0;
+    while ip < program.len() {
+        let op = program[ip];
+        let mut words = vec![Word::Op(op as u8)];
+        // Number of input words the instruction replaces
+        let len = if foldable.contains(&op) {
+            let len = program[ip..]
+                .iter()
+                .take(MAX_RUN)
+                .take_while(|w| **w == op)
+                .count();
+            words.push(Word::Arg(len as u64));
+            len
+        } else if op == OPCODES[LB] as u64 {
+            // The loop ends with RB and its jump target, right before the LB target
+            let target = word(ip + 1) as usize;
+            match program
+                .get(ip + 2..target.saturating_sub(2))
+                .and_then(macro_loop)
+            {
+                Some(targets) => {
+                    words = macro_words(&targets, target);
+                    target - ip
+                }
+                None => {
+                    words.push(Word::Target(target));
+                    2
+                }
+            }
+        } else if op == OPCODES[RB] as u64 {
+            words.push(Word::Target(word(ip + 1) as usize));
+            2
+        } else {
+            1
+        };
+        ips.extend(std::iter::repeat(folded_ip).take(len));
+        folded_ip += words.len();
+        instructions.push((ip, words));
+        ip += len;
+    }
+    // The halting row sits right past the program
+    ips.push(folded_ip);
+
+    let mut words = Vec::with_capacity(folded_ip);
+    let mut origins = Vec::with_capacity(folded_ip);
+    for (ip, instruction) in instructions {
+        for word in instruction {
+            words.push(match word {
+                Word::Op(op) => op as u64,
+                Word::Arg(arg) => arg,
+                // Past the program for words that are not `code::compile` output
+                Word::Target(target) => ips.get(target).copied().unwrap_or(folded_ip) as u64,
+            });
+            origins.push(ip);
+        }
+    }
+    Layout { words, origins }
+}
+
+/// The offsets that receive the value of the current cell if the body of a loop makes it a
+/// macro instruction, none for a clear loop. The body must not move the pointer overall,
+/// and decrement the current cell once per iteration while incrementing the targets once.
+fn macro_loop(body: &[u64]) -> Option<Vec<i64>> {
+    let [shl, shr, add, sub] = [SHL, SHR, ADD, SUB].map(|op| OPCODES[op] as u64);
+    let mut offset = 0i64;
+    let mut deltas = BTreeMap::new();
+    for word in body {
+        match *word {
+            w if w == shl => offset -= 1,
+            w if w == shr => offset += 1,
+            w if w == add => *deltas.entry(offset).or_insert(0i64) += 1,
+            w if w == sub => *deltas.entry(offset).or_insert(0i64) -= 1,
+            // Nested loops, GETCHAR and PUTCHAR
+            _ => return None,
+        }
+    }
+    deltas.retain(|_, delta| *delta != 0);
+    if offset != 0 {
+        return None;
+    }
+    match deltas.remove(&0) {
+        // [+] clears the cell too, by wrapping around
+        Some(-1) | Some(1) if deltas.is_empty() => Some(vec![]),
+        Some(-1) if deltas.values().all(|delta| *delta == 1) => Some(deltas.into_keys().collect()),
+        _ => None,
+    }
+}
+
+/// Words of a macro loop, `end` is the input ip past the loop. The shifts of a move loop are
+/// skipped on a cell holding 0, so they never pass below cell 0 when the loop would not run.
+fn macro_words(targets: &[i64], end: usize) -> Vec<Word> {
+    let mut words = Vec::new();
+    if !targets.is_empty() {
+        words.push(Word::Op(OPCODES[LB]));
+        words.push(Word::Target(end));
+    }
+    words.push(Word::Op(OPCODES[CLEAR]));
+    let mut offset = 0;
+    for target in targets {
+        shift(&mut words, target - offset);
+        words.push(Word::Op(OPCODES[MOVE]));
+        offset = *target;
+    }
+    shift(&mut words, -offset);
+    words
+}
+
+fn shift(words: &mut Vec<Word>, by: i64) {
+    let op = if by > 0 { OPCODES[SHR] } else { OPCODES[SHL] };
+    let mut left = by.unsigned_abs() as usize;
+    while left > 0 {
+        let len = left.min(MAX_RUN);
+        words.push(Word::Op(op));
+        words.push(Word::Arg(len as u64));
+        left -= len;
+    }
+}
+
+/// The folded program of a `code::compile` program, other words fold into some program too.
+pub fn compile(program: &[u64]) -> Vec<u64> {
+    layout(program).words
+}
+
+/// A row of the processor table of a folded program, clk is its index.
+pub(crate) struct Step {
+    pub ip: u64,
+    pub ci: u64,
+    pub ni: u64,
+    pub mp: u64,
+    pub mv: u64,
+}
+
+/**
+ * Execute a folded program on `input` and return its rows, up to the halting row.
+ *
+ * Fails with `Error::StepLimit` as soon as there are more than `max_steps` rows, so a program
+ * that does not halt is stopped, and with `Error::Interpreter` if the program reads past the
+ * end of the input or accesses a cell below 0.
+ */
+pub(crate) fn execute(words: &[u64], input: &[u64], max_steps: Option<usize>) -> Result<Vec<Step>> {
+    let word = |ip: usize| words.get(ip).copied().unwrap_or(0);
+    let op = |op: usize| OPCODES[op] as u64;
+    let cells = DOMAIN as i64;
+
+    let mut steps = Vec::new();
+    let mut memory = BTreeMap::new();
+    let mut input = input.iter();
+    let (mut ip, mut mp, mut acc) = (0, 0i64, 0);
+    loop {
+        if let Some(limit) = max_steps.filter(|limit| steps.len() >= *limit) {
+            return Err(Error::StepLimit { limit });
+        }
+        let (ci, ni) = (word(ip), word(ip + 1));
+        let shift = [SHR, SHL].map(op).contains(&ci);
+        if mp < 0 && ip < words.len() && !shift {
+            return Err(Error::Interpreter(format!(
+                "the memory pointer is below cell 0 at ip {}",
+                ip
+            )));
+        }
+        let mv = memory.get(&mp).copied().unwrap_or(0);
+        steps.push(Step {
+            ip: ip as u64,
+            ci,
+            ni,
+            mp: mp as u64,
+            mv: mv as u64,
+        });
+        if ip >= words.len() {
+            return Ok(steps);
+        }
+
+        let mut next_mv = mv;
+        let mut next_mp = mp;
+        // Instructions followed by an argument or a jump target take two words
+        let mut next_ip = if [ADD, SUB, SHR, SHL, LB, RB].map(op).contains(&ci) {
+            ip + 2
+        } else {
+            ip + 1
+        };
+        match ci {
+            ci if ci == op(ADD) => next_mv = (mv + ni as i64) % cells,
+            ci if ci == op(SUB) => next_mv = (mv + cells - ni as i64 % cells) % cells,
+            ci if ci == op(SHR) => next_mp = mp + ni as i64,
+            ci if ci == op(SHL) => next_mp = mp - ni as i64,
+            ci if ci == op(GETCHAR) => match input.next() {
+                Some(byte) => next_mv = *byte as i64,
+                None => {
+                    return Err(Error::Interpreter(format!(
+                        "the program reads past the end of the input at ip {}",
+                        ip
+                    )))
+                }
+            },
+            ci if ci == op(LB) && mv == 0 => next_ip = ni as usize,
+            ci if ci == op(RB) && mv != 0 => next_ip = ni as usize,
+            ci if ci == op(CLEAR) => {
+                acc = mv;
+                next_mv = 0;
+            }
+            ci if ci == op(MOVE) => next_mv = (mv + acc) % cells,
+            // PUTCHAR, and brackets that do not jump
+            _ => {}
+        }
+        memory.insert(mp, next_mv);
+        mp = next_mp;
+        ip = next_ip;
+    }
+}
+
+/**
+ * Execute a folded program on `input` and return its trace, see `execute` for the errors.
+ *
+ * The memory table is the processor table sorted by (mp, clk), and the instruction table
+ * holds the words of the program and the rows of the processor table, sorted by ip.
+ * The input table holds the bytes read by GETCHAR, the output table the bytes printed by PUTCHAR.
+ */
+pub(crate) fn trace(words: &[u64], input: &[u64], max_steps: Option<usize>) -> Result<Matrix> {
+    let steps = execute(words, input, max_steps)?;
+    let word = |ip: usize| words.get(ip).copied().unwrap_or(0);
+    let [getchar, putchar] = [GETCHAR, PUTCHAR].map(|op| OPCODES[op] as u64);
+
+    let processor_matrix = steps
+        .iter()
+        .enumerate()
+        .map(|(clk, step)| Register {
+            cycle: Fq::from(clk as u64),
+            instruction_pointer: Fq::from(step.ip),
+            current_instruction: Fq::from(step.ci),
+            next_instruction: Fq::from(step.ni),
+            memory_pointer: Fq::from(step.mp),
+            memory_value: Fq::from(step.mv),
+            memory_value_inverse: Fq::from(step.mv).invert().unwrap_or(Fq::zero()),
+        })
+        .collect::<Vec<_>>();
+
+    let mut memory_matrix = processor_matrix
+        .iter()
+        .map(|reg| MemoryMatrixRow {
+            cycle: reg.cycle,
+            memory_pointer: reg.memory_pointer,
+            memory_value: reg.memory_value,
+        })
+        .collect::<Vec<_>>();
+    memory_matrix.sort_by_key(|row| (to_u64(row.memory_pointer), to_u64(row.cycle)));
+
+    // Program rows first, then the rows of the processor table, sorted by ip
+    let mut instruction_matrix = (0..words.len())
+        .map(|ip| (ip as u64, word(ip), word(ip + 1)))
+        .chain(steps.iter().map(|step| (step.ip, step.ci, step.ni)))
+        .map(|(ip, ci, ni)| InstructionMatrixRow {
+            instruction_pointer: Fq::from(ip),
+            current_instruction: Fq::from(ci),
+            next_instruction: Fq::from(ni),
+        })
+        .collect::<Vec<_>>();
+    instruction_matrix.sort_by_key(|row| to_u64(row.instruction_pointer));
+
+    let reads = steps.iter().filter(|step| step.ci == getchar).count();
+    Ok(Matrix {
+        processor_matrix,
+        memory_matrix,
+        instruction_matrix,
+        input_matrix: input[..reads].iter().map(|v| Fq::from(*v)).collect(),
+        output_matrix: steps
+            .iter()
+            .filter(|step| step.ci == putchar)
+            .map(|step| Fq::from(step.mv))
+            .collect(),
+        ..Default::default()
+    })
+}
+
+/**
+ * Rewrite the trace of a `code::compile` program into the trace of its folded program.
+ *
+ * The folded program is executed again on the input the trace consumed, see `trace`. It never
+ * takes more rows than the trace, so a trace that its folded program does not follow fails
+ * with the errors of `execute`, and an empty one with `Error::Compile`.
+ */
+pub fn fold(matrix: &mut Matrix) -> Result<()> {
+    check_trace(matrix).map_err(|message| Error::Compile {
+        position: 0,
+        message,
+    })?;
+    let program = program::<Fq>(matrix)
+        .into_iter()
+        .map(to_u64)
+        .collect::<Vec<_>>();
+    let input = matrix
+        .input_matrix
+        .iter()
+        .map(|v| to_u64(*v))
+        .collect::<Vec<_>>();
+    *matrix = trace(
+        &compile(&program),
+        &input,
+        Some(matrix.processor_matrix.len()),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,501 @@
+use crate::error;
+use crate::image_table::ImageTableConfig;
+use crate::io_table::{InputTableConfig, OutputTableConfig};
+use crate::main_config::{
+    check_rows, check_trace, constrain_terminals, lookup_memory, lookup_program, public_instances,
+    usable_rows,
+};
+use crate::memory_table::MemoryTableConfig;
+use crate::opcode_table::OpcodeTableConfig;
+use crate::padding::pad;
+use crate::processor_table::ProcessorTableConfig;
+use crate::program_table::ProgramTableConfig;
+use crate::prover::{self, TranscriptKind};
+use crate::range_table::RangeTableConfig;
+use crate::utils::*;
+use ckb_bf_zkvm::matrix::Matrix;
+
+use halo2_proofs::arithmetic::FieldExt;
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::halo2curves::bn256::{Bn256, Fq, Fr, G1Affine};
+use halo2_proofs::plonk::*;
+use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+use halo2_proofs::poly::Rotation;
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+
+/**
+ * State of the machine between two segments of an execution: the registers of the first row
+ * of the next segment, the number of bytes read and printed so far and the memory, one byte
+ * per cell without the trailing zeros.
+ */
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Boundary {
+    pub clk: u64,
+    pub ip: u64,
+    pub mp: u64,
+    pub acc: u64,
+    pub input_offset: u64,
+    pub output_offset: u64,
+    #[serde(with = "hex::serde")]
+    pub memory: Vec<u8>,
+}
+
+impl Boundary {
+    /// Blake2b-256 of every field in declaration order, integers as 8 bytes little endian.
+    pub fn commitment(&self) -> [u8; 32] {
+        let mut state = blake2b_simd::Params::new().hash_length(32).to_state();
+        for value in [
+            self.clk,
+            self.ip,
+            self.mp,
+            self.acc,
+            self.input_offset,
+            self.output_offset,
+        ] {
+            state.update(&value.to_le_bytes());
+        }
+        state.update(&self.memory);
+        state.finalize().as_bytes().try_into().unwrap()
+    }
+
+    /// The registers the segment circuit exposes in its boundary instance.
+    fn registers<F: FieldExt>(&self) -> [F; 4] {
+        [self.clk, self.ip, self.mp, self.acc].map(F::from)
+    }
+
+    fn memory<F: FieldExt>(&self) -> Vec<F> {
+        self.memory.iter().map(|v| F::from(*v as u64)).collect()
+    }
+}
+
+/// Public instances of a segment: those of `MyCircuit` for the bytes read and printed within
+/// the segment, then the memory at both ends and the registers at both ends.
+pub fn segment_instances<F: FieldExt>(
+    program: Vec<F>,
+    start: &Boundary,
+    end: &Boundary,
+    input: &[u8],
+    output: &[u8],
+) -> Vec<Vec<F>> {
+    let to_fields = |bytes: &[u8]| -> Vec<F> { bytes.iter().map(|v| F::from(*v as u64)).collect() };
+    let mut instances = public_instances(program, to_fields(input), to_fields(output));
+    instances.push(start.memory());
+    instances.push(end.memory());
+    instances.push(
+        start
+            .registers()
+            .into_iter()
+            .chain(end.registers())
+            .collect(),
+    );
+    instances
+}
+
+/**
+ * Processor and memory tables of a segment, see `SegmentCircuit`. The processor table does not
+ * start from the reset state, and the memory table looks up the value of each cell when the
+ * segment starts in the image table, which also checks the memory when the segment ends.
+ */
+#[derive(Clone, Debug, Copy)]
+pub struct SegmentConfig<const RANGE: usize> {
+    p_config: ProcessorTableConfig<RANGE>,
+    m_config: MemoryTableConfig,
+    program_config: ProgramTableConfig,
+    input_config: InputTableConfig,
+    output_config: OutputTableConfig,
+    image_config: ImageTableConfig,
+    boundary: Column<Instance>, // clk, ip, mp and acc of the first row, then of the last row
+}
+
+impl<const RANGE: usize> SegmentConfig<RANGE> {
+    pub fn configure<F: FieldExt>(cs: &mut ConstraintSystem<F>, challenges: Challenges) -> Self {
+        let lookup_table = RangeTableConfig::<RANGE>::configure(cs, challenges);
+        let opcode_table = OpcodeTableConfig::configure(cs, challenges);
+        let p_config = ProcessorTableConfig::configure_group(
+            cs,
+            challenges,
+            lookup_table,
+            opcode_table,
+            false,
+        );
+        let m_config = MemoryTableConfig::configure_group(cs, challenges, true);
+        let program_config = ProgramTableConfig::configure(cs, challenges);
+        let input_config = InputTableConfig::configure(cs, challenges);
+        let output_config = OutputTableConfig::configure(cs, challenges);
+        let image_config = ImageTableConfig::configure(cs, challenges);
+        let boundary = cs.instance_column();
+        cs.enable_equality(boundary);
+
+        lookup_memory(cs, m_config, lookup_table);
+        lookup_program(cs, p_config, program_config);
+
+        // The image table has a row per cell, so this also bounds mp
+        let start_v = m_config.segment.unwrap().start_v;
+        cs.lookup_any(
+            "Image: memory (mp, start_v) is the value of the cell when the segment starts",
+            |vc| {
+                let s_memory = vc.query_selector(m_config.s_lookup);
+                let s_image = vc.query_selector(image_config.s_lookup);
+                let m_mp = vc.query_advice(m_config.mp, Rotation::cur());
+                let m_start_v = vc.query_advice(start_v, Rotation::cur());
+                let image_mp = vc.query_advice(image_config.mp, Rotation::cur());
+                let image_start = vc.query_advice(image_config.start, Rotation::cur());
+                vec![
+                    (s_memory.clone() * m_mp, s_image.clone() * image_mp),
+                    (s_memory * m_start_v, s_image * image_start),
+                ]
+            },
+        );
+
+        Self {
+            p_config,
+            m_config,
+            program_config,
+            input_config,
+            output_config,
+            image_config,
+            boundary,
+        }
+    }
+
+    /// Load the tables of a segment whose first row holds `acc`, every table spans every row.
+    pub fn load_table<F: FieldExt>(
+        &self,
+        layouter: &mut impl Layouter<F>,
+        matrix: &Matrix,
+        acc: F,
+    ) -> Result<(), Error> {
+        let height = height(matrix, 1);
+        // Only the last segment halts, which `verify_chain` checks with its ip
+        let (p_cells, p_terminals) = self
+            .p_config
+            .assign_group(layouter, matrix, 0, height, acc, false)?;
+        let (_, m_terminals) = self.m_config.assign_group(layouter, matrix, 0, height)?;
+        let input_re = self.input_config.assign_table(layouter, matrix, height)?;
+        let output_re = self.output_config.assign_table(layouter, matrix, height)?;
+        let irp = self.image_config.assign_table(layouter, matrix, height)?;
+        let erp = m_terminals.erp.ok_or(Error::Synthesis)?;
+        constrain_terminals(
+            layouter,
+            &p_terminals,
+            &m_terminals.prp,
+            &input_re,
+            &output_re,
+        )?;
+        // The memory at the end is the memory at the start updated by the memory table
+        layouter.assign_region(
+            || "RP: memory table and image table",
+            |mut region| region.constrain_equal(erp.cell(), irp.cell()),
+        )?;
+        // clk, ip, mp and acc of the linked cells
+        for (row, idx) in [0, 1, 4, 6].into_iter().enumerate() {
+            layouter.constrain_instance(p_cells.first[idx].cell(), self.boundary, row)?;
+            layouter.constrain_instance(p_cells.last[idx].cell(), self.boundary, row + 4)?;
+        }
+        self.program_config.assign_table(layouter, matrix, height)
+    }
+}
+
+/**
+ * A segment of an execution, proven on its own. Segments span the usable rows of the
+ * domain and overlap by one row: the last row of a segment is the first row of the next one.
+ * Only the last segment is padded with halting rows. The registers and the memory at both
+ * ends of the segment are public, so `verify_chain` checks that the segments make up one
+ * execution, like `MyCircuit` does for the column groups of a single proof.
+ */
+#[derive(Default)]
+pub struct SegmentCircuit<F: FieldExt, const RANGE: usize> {
+    _marker: PhantomData<F>,
+    matrix: Matrix,
+    start: Boundary,
+    end: Boundary,
+    k: u32,
+}
+
+impl<F: FieldExt, const RANGE: usize> SegmentCircuit<F, RANGE> {
+    /**
+     * Cut the trace of an execution into segments of 2^k rows. Every segment only accesses
+     * the cells below its usable rows, which hold the memory image, and reads and prints fewer
+     * bytes than that.
+     */
+    pub fn split(matrix: &Matrix, k: u32) -> error::Result<Vec<Self>> {
+        check_trace(matrix).map_err(error::Error::InvalidChain)?;
+        let height = Self::usable_rows(k);
+        let processor_matrix = &matrix.processor_matrix;
+        let len = processor_matrix.len();
+        // The image table has a row per cell
+        let rows = processor_matrix
+            .iter()
+            .map(|reg| (to_u64(reg.memory_pointer) as usize).saturating_add(1))
+            .chain([program::<Fq>(matrix).len() + 1, RANGE, OPCODES.len() + 1])
+            .max()
+            .unwrap();
+        check_rows::<F, Self>(rows, k)?;
+
+        let getchar = OPCODES[GETCHAR] as u64;
+        let putchar = OPCODES[PUTCHAR] as u64;
+        let clear = OPCODES[CLEAR] as u64;
+        let mut memory = vec![0u8; height];
+        let mut state = Boundary::default();
+        let mut segments = Vec::new();
+        let mut row = 0;
+        loop {
+            let end = (row + height).min(len);
+            let regs = &processor_matrix[row..end];
+            let start = state.clone();
+            // The last row of the segment is the first row of the next one
+            for reg in &regs[..regs.len() - 1] {
+                let ci = to_u64(reg.current_instruction);
+                memory[to_u64(reg.memory_pointer) as usize] = to_u64(reg.memory_value) as u8;
+                if ci == clear {
+                    state.acc = to_u64(reg.memory_value);
+                }
+                state.input_offset += (ci == getchar) as u64;
+                state.output_offset += (ci == putchar) as u64;
+            }
+            let last = &regs[regs.len() - 1];
+            memory[to_u64(last.memory_pointer) as usize] = to_u64(last.memory_value) as u8;
+            state.ip = to_u64(last.instruction_pointer);
+            state.mp = to_u64(last.memory_pointer);
+            state.memory = memory.clone();
+            while state.memory.last() == Some(&0) {
+                state.memory.pop();
+            }
+
+            let input = start.input_offset as usize..state.input_offset as usize;
+            let output = start.output_offset as usize..state.output_offset as usize;
+            if input.len() >= height || output.len() >= height {
+                let rows = input.len().max(output.len()) + 1;
+                check_rows::<F, Self>(rows, k)?;
+            }
+            // The memory table of the rows, sorted by (mp, clk) like `rle::trace` does
+            let template = matrix.memory_matrix[0].clone();
+            let mut memory_matrix = regs
+                .iter()
+                .map(|reg| {
+                    let mut row = template.clone();
+                    row.cycle = reg.cycle;
+                    row.memory_pointer = reg.memory_pointer;
+                    row.memory_value = reg.memory_value;
+                    row
+                })
+                .collect::<Vec<_>>();
+            memory_matrix.sort_by_key(|row| (to_u64(row.memory_pointer), to_u64(row.cycle)));
+            let mut segment = Matrix {
+                processor_matrix: regs.to_vec(),
+                memory_matrix,
+                instruction_matrix: matrix.instruction_matrix.clone(),
+                input_matrix: matrix.input_matrix[input].to_vec(),
+                output_matrix: matrix.output_matrix[output].to_vec(),
+                ..Default::default()
+            };
+            pad(&mut segment, height, 1);
+            // Padding extends the last segment, which ends on its last halting row
+            state.clk = to_u64(segment.processor_matrix[height - 1].cycle);
+            segments.push(Self {
+                _marker: PhantomData,
+                matrix: segment,
+                start,
+                end: state.clone(),
+                k,
+            });
+            if end == len {
+                return Ok(segments);
+            }
+            row = end - 1;
+        }
+    }
+
+    /// The circuit has 2^k rows.
+    pub fn k(&self) -> u32 {
+        self.k
+    }
+
+    /// The rows of the segment, padded to the usable rows of the domain.
+    pub fn matrix(&self) -> &Matrix {
+        &self.matrix
+    }
+
+    /// State of the machine when the segment starts.
+    pub fn start(&self) -> &Boundary {
+        &self.start
+    }
+
+    /// State of the machine when the segment ends.
+    pub fn end(&self) -> &Boundary {
+        &self.end
+    }
+
+    /// The bytes read by GETCHAR within the segment.
+    pub fn input(&self) -> Vec<u8> {
+        to_bytes(&self.matrix.input_matrix)
+    }
+
+    /// The bytes printed by PUTCHAR within the segment.
+    pub fn output(&self) -> Vec<u8> {
+        to_bytes(&self.matrix.output_matrix)
+    }
+
+    /// Number of rows the tables can use in a domain of 2^k rows, which is also
+    /// the number of rows of a segment.
+    pub fn usable_rows(k: u32) -> usize {
+        usable_rows::<F, Self>(k)
+    }
+
+    /// Public instances of the segment, see `segment_instances`.
+    pub fn instances(&self) -> Vec<Vec<F>> {
+        segment_instances(
+            program(&self.matrix),
+            &self.start,
+            &self.end,
+            &self.input(),
+            &self.output(),
+        )
+    }
+}
+
+impl<F: FieldExt, const RANGE: usize> Circuit<F> for SegmentCircuit<F, RANGE> {
+    type Config = SegmentConfig<RANGE>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let challenges = Challenges::construct(meta);
+        SegmentConfig::configure(meta, challenges)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        config.load_table(&mut layouter, &self.matrix, F::from(self.start.acc))
+    }
+}
+
+/// A proof of a segment together with its public data, except for the program.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentProof {
+    pub start: Boundary,
+    pub end: Boundary,
+    #[serde(with = "hex::serde")]
+    pub input: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub output: Vec<u8>,
+    #[serde(default)]
+    pub transcript: TranscriptKind,
+    #[serde(with = "hex::serde")]
+    pub proof: Vec<u8>,
+}
+
+impl SegmentProof {
+    pub(crate) fn new<const RANGE: usize>(
+        circuit: &SegmentCircuit<Fr, RANGE>,
+        transcript: TranscriptKind,
+        proof: Vec<u8>,
+    ) -> Self {
+        Self {
+            start: circuit.start().clone(),
+            end: circuit.end().clone(),
+            input: circuit.input(),
+            output: circuit.output(),
+            transcript,
+            proof,
+        }
+    }
+}
+
+/**
+ * Verify that the segments prove one execution of `program`, which reads `input` and prints
+ * `output`: the first segment starts from the reset state, every segment starts where the
+ * previous one ends, their bytes make up the input and the output, the last one halts, and
+ * every proof is valid. The proofs are checked as a batch, see `prover::verify_accumulated`,
+ * so they must all use the same transcript.
+ */
+pub fn verify_chain(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    program: &[Fr],
+    input: &[u8],
+    output: &[u8],
+    segments: &[SegmentProof],
+) -> error::Result<()> {
+    let invalid = |message: String| Err(error::Error::InvalidChain(message));
+    let (first, last) = match (segments.first(), segments.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return invalid("there are no segments".to_string()),
+    };
+    if first.start != Boundary::default() {
+        return invalid("the first segment does not start from the reset state".to_string());
+    }
+    for (idx, pair) in segments.windows(2).enumerate() {
+        if pair[0].end.commitment() != pair[1].start.commitment() {
+            return invalid(format!(
+                "segment {} does not start where segment {} ends",
+                idx + 1,
+                idx
+            ));
+        }
+    }
+    for (idx, segment) in segments.iter().enumerate() {
+        let bytes = |all: &[u8], start: u64, end: u64| {
+            all.get(start as usize..end as usize)
+                .map(|bytes| bytes.to_vec())
+        };
+        if bytes(input, segment.start.input_offset, segment.end.input_offset).as_ref()
+            != Some(&segment.input)
+        {
+            return invalid(format!("segment {} reads other bytes of the input", idx));
+        }
+        if bytes(
+            output,
+            segment.start.output_offset,
+            segment.end.output_offset,
+        )
+        .as_ref()
+            != Some(&segment.output)
+        {
+            return invalid(format!("segment {} prints other bytes of the output", idx));
+        }
+    }
+    if last.end.input_offset != input.len() as u64 || last.end.output_offset != output.len() as u64
+    {
+        return invalid(
+            "the segments do not read the whole input or print the whole output".to_string(),
+        );
+    }
+    if last.end.ip != program.len() as u64 {
+        return invalid("the last segment does not halt".to_string());
+    }
+    if let Some(idx) = segments
+        .iter()
+        .position(|segment| segment.transcript != first.transcript)
+    {
+        return invalid(format!(
+            "segment {} uses another transcript than segment 0",
+            idx
+        ));
+    }
+
+    let proofs = segments
+        .iter()
+        .map(|segment| segment.proof.clone())
+        .collect::<Vec<_>>();
+    let instances = segments
+        .iter()
+        .map(|segment| {
+            segment_instances(
+                program.to_vec(),
+                &segment.start,
+                &segment.end,
+                &segment.input,
+                &segment.output,
+            )
+        })
+        .collect::<Vec<_>>();
+    prover::verify_accumulated(params, vk, &proofs, &instances, first.transcript)
+}
@@ -1,10 +1,12 @@
 use ckb_bf_zkvm::code;
 use ckb_bf_zkvm::matrix::Matrix;
-use halo2_proofs::circuit::Layouter;
+use halo2_proofs::arithmetic::FieldExt;
+use halo2_proofs::circuit::{AssignedCell, Layouter, Region};
 use halo2_proofs::halo2curves::bn256::Fq;
+use halo2_proofs::halo2curves::group::ff::PrimeField;
 use halo2_proofs::plonk::*;
 
-pub const OPCODES: [u8; 8] = [
+pub const OPCODES: [u8; 10] = [
     code::SHL,
     code::SHR,
     code::ADD,
@@ -13,6 +15,9 @@ pub const OPCODES: [u8; 8] = [
     code::PUTCHAR,
     code::LB,
     code::RB,
+    // Macro instructions of the folded program, they are not Brainfuck characters
+    b'c',
+    b'm',
 ];
 
 pub const SHL: usize = 0;
@@ -23,10 +28,103 @@ pub const GETCHAR: usize = 4;
 pub const PUTCHAR: usize = 5;
 pub const LB: usize = 6;
 pub const RB: usize = 7;
+pub const CLEAR: usize = 8;
+pub const MOVE: usize = 9;
 
 pub const DOMAIN: usize = 256;
 
-pub trait Config {
-    fn configure(cs: &mut ConstraintSystem<Fq>) -> Self;
-    fn load_table(&self, layouter: &mut impl Layouter<Fq>, matrix: &Matrix) -> Result<(), Error>;
+/// Version of the constraints, bumped whenever a change makes old keys and proofs invalid.
+pub const CIRCUIT_VERSION: u32 = 6;
+
+/// Largest circuit size `MyCircuit::min_k` picks, 2^26 rows.
+pub const MAX_K: u32 = 26;
+
+/// Verifier challenges shared by the cross-table arguments.
+/// They are drawn after all first phase columns are committed.
+#[derive(Clone, Debug, Copy)]
+pub struct Challenges {
+    pub beta: Challenge,    // Shift of the running product (prp)
+    pub gamma: Challenge,   // Compress a tuple into a single field element
+    pub delta: Challenge,   // Evaluation point of the input running evaluation (re)
+    pub epsilon: Challenge, // Evaluation point of the output running evaluation (re)
+}
+
+impl Challenges {
+    pub fn construct<F: FieldExt>(cs: &mut ConstraintSystem<F>) -> Self {
+        Self {
+            beta: cs.challenge_usable_after(FirstPhase),
+            gamma: cs.challenge_usable_after(FirstPhase),
+            delta: cs.challenge_usable_after(FirstPhase),
+            epsilon: cs.challenge_usable_after(FirstPhase),
+        }
+    }
+}
+
+/// The interpreter records its trace over bn256::Fq, while the circuit can be defined
+/// over any field. Trace values are small integers, so they are the same in every field.
+pub fn to_field<F: FieldExt>(v: Fq) -> F {
+    F::from(to_u64(v))
+}
+
+/// A small trace value as an integer, e.g. an ip or a cycle.
+pub fn to_u64(v: Fq) -> u64 {
+    u64::from_le_bytes(v.to_repr()[..8].try_into().unwrap())
+}
+
+/// The bytes of the input or output matrix.
+pub fn to_bytes(values: &[Fq]) -> Vec<u8> {
+    values.iter().map(|v| v.to_repr()[0]).collect()
+}
+
+/// The compiled program, recovered from the instruction matrix. The instruction
+/// matrix is sorted by ip and each program row comes before its processor copies.
+/// Halting rows of the processor table have ci = 0, which is never a word of the program.
+pub fn program<F: FieldExt>(matrix: &Matrix) -> Vec<F> {
+    let instruction_matrix = &matrix.instruction_matrix;
+    instruction_matrix
+        .iter()
+        .enumerate()
+        .filter(|(idx, row)| {
+            *idx == 0 || instruction_matrix[idx - 1].instruction_pointer != row.instruction_pointer
+        })
+        .map(|(_, row)| to_field(row.current_instruction))
+        .filter(|ci: &F| *ci != F::zero())
+        .collect()
+}
+
+/// Number of rows every table spans, once the processor table is padded. The processor
+/// and memory tables are folded into `groups` column groups, and each group repeats the
+/// last row of the previous one.
+pub fn height(matrix: &Matrix, groups: usize) -> usize {
+    matrix.processor_matrix.len().saturating_sub(1) / groups + 1
+}
+
+/// Name of the region holding a column group of a table, the first group keeps the table name.
+pub fn region_name(table: &str, group: usize) -> String {
+    match group {
+        0 => table.to_string(),
+        _ => format!("{} (group {})", table, group),
+    }
+}
+
+/// Cells of the first and the last row of a column group, one per linked column.
+pub struct GroupCells<F: FieldExt> {
+    pub first: Vec<AssignedCell<F, F>>,
+    pub last: Vec<AssignedCell<F, F>>,
+}
+
+impl<F: FieldExt> GroupCells<F> {
+    /// The first row of `next` repeats the last row of this group, so the transition
+    /// out of that row is checked by the gates of `next`.
+    pub fn link(&self, region: &mut Region<'_, F>, next: &Self) -> Result<(), Error> {
+        for (last, first) in self.last.iter().zip(&next.first) {
+            region.constrain_equal(last.cell(), first.cell())?;
+        }
+        Ok(())
+    }
+}
+
+pub trait Config<F: FieldExt> {
+    fn configure(cs: &mut ConstraintSystem<F>, challenges: Challenges) -> Self;
+    fn load_table(&self, layouter: &mut impl Layouter<F>, matrix: &Matrix) -> Result<(), Error>;
 }
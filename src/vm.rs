@@ -0,0 +1,78 @@
+use crate::error::{Error, Result};
+use crate::rle;
+use crate::utils::{to_u64, LB, OPCODES, RB};
+
+use ckb_bf_zkvm::code;
+use ckb_bf_zkvm::matrix::Matrix;
+use halo2_proofs::arithmetic::FieldExt;
+
+/// Check that the brackets of a Brainfuck source are balanced, `code::compile` assumes they are.
+pub fn check(source: &[u8]) -> Result<()> {
+    let mut open = Vec::new();
+    for (position, c) in source.iter().enumerate() {
+        match *c {
+            b'[' => open.push(position),
+            b']' => {
+                if open.pop().is_none() {
+                    return Err(Error::Compile {
+                        position,
+                        message: "unmatched ]".to_string(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    match open.pop() {
+        Some(position) => Err(Error::Compile {
+            position,
+            message: "unmatched [".to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Words of `code::compile` output, before runs are folded.
+fn words(source: &[u8]) -> Vec<u64> {
+    code::compile(source.to_vec())
+        .into_iter()
+        .map(to_u64)
+        .collect()
+}
+
+/// The compiled program with its runs folded, as the verifier puts it into the program instance.
+pub fn compile<F: FieldExt>(source: &[u8]) -> Result<Vec<F>> {
+    check(source)?;
+    Ok(rle::compile(&words(source))
+        .into_iter()
+        .map(F::from)
+        .collect())
+}
+
+/// Byte offset in the source of every word of the compiled program. Arguments and jump
+/// targets are mapped to their instruction, a folded run to its first character.
+pub fn positions(source: &[u8]) -> Vec<usize> {
+    let mut positions = Vec::new();
+    for (position, c) in source.iter().enumerate() {
+        if !OPCODES[..=RB].contains(c) {
+            continue;
+        }
+        positions.push(position);
+        if *c == OPCODES[LB] || *c == OPCODES[RB] {
+            positions.push(position);
+        }
+    }
+    rle::layout(&words(source))
+        .origins
+        .into_iter()
+        .map(|ip| positions[ip])
+        .collect()
+}
+
+/// Run a Brainfuck source on `input` and return the trace of its folded program, see
+/// `rle::trace`. A program that does not halt within `max_steps` rows fails.
+pub fn run(source: &[u8], input: &[u8], max_steps: Option<usize>) -> Result<Matrix> {
+    check(source)?;
+    let bytes = input.iter().map(|b| *b as u64).collect::<Vec<_>>();
+    rle::trace(&rle::compile(&words(source)), &bytes, max_steps)
+}
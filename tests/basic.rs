@@ -1,9 +1,13 @@
 use ckb_bf_zkvm::code;
 use ckb_bf_zkvm::interpreter::Interpreter;
+use ckb_bf_zkvm::matrix::Matrix;
 use halo2_bf::main_config::MyCircuit;
-use halo2_bf::utils::DOMAIN;
+use halo2_bf::rle;
+use halo2_bf::utils::{to_bytes, to_u64, DOMAIN, MAX_K};
+use halo2_bf::{vm, Error};
 use halo2_proofs::dev::MockProver;
-use halo2_proofs::halo2curves::bn256::Fq;
+use halo2_proofs::halo2curves::bn256::{Fq, Fr};
+use halo2_proofs::halo2curves::pasta::Fp;
 
 #[test]
 fn test_prove_hello_world() {
@@ -11,9 +15,25 @@ fn test_prove_hello_world() {
     let mut vm = Interpreter::new();
     vm.set_code(program);
     vm.run();
+    rle::fold(&mut vm.matrix).unwrap();
 
-    let circuit = MyCircuit::<Fq, { DOMAIN }>::new(vm.matrix);
-    let prover = MockProver::run(11, &circuit, vec![]).unwrap();
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&vm.matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(vm.matrix, k).unwrap();
+    let prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn test_prove_hello_world_pasta() {
+    let program = code::compile(include_bytes!("../ckb-bf-zkvm/res/hello_world.bf").to_vec());
+    let mut vm = Interpreter::new();
+    vm.set_code(program);
+    vm.run();
+    rle::fold(&mut vm.matrix).unwrap();
+
+    let k = MyCircuit::<Fp, { DOMAIN }>::min_k(&vm.matrix).unwrap();
+    let circuit = MyCircuit::<Fp, { DOMAIN }>::new(vm.matrix, k).unwrap();
+    let prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
     prover.assert_satisfied();
 }
 
@@ -24,21 +44,95 @@ fn test_prove_neptune() {
     vm.set_code(program);
     vm.set_input(code::easygen("a"));
     vm.run();
+    rle::fold(&mut vm.matrix).unwrap();
 
-    let circuit = MyCircuit::<Fq, { DOMAIN }>::new(vm.matrix);
-    let prover = MockProver::run(10, &circuit, vec![]).unwrap();
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&vm.matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(vm.matrix, k).unwrap();
+    let prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
     prover.assert_satisfied();
 }
 
+#[test]
+fn test_forged_input() {
+    let program = code::compile(include_bytes!("../ckb-bf-zkvm/res/neptune_tutorial.bf").to_vec());
+    let mut vm = Interpreter::new();
+    vm.set_code(program);
+    vm.set_input(code::easygen("a"));
+    vm.run();
+    rle::fold(&mut vm.matrix).unwrap();
+    // Claim a different input than the one GETCHAR actually consumed
+    vm.matrix.input_matrix[0] = Fq::from(b'b' as u64);
+
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&vm.matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(vm.matrix, k).unwrap();
+    let prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn test_forged_output() {
+    let program = code::compile(include_bytes!("../ckb-bf-zkvm/res/hello_world.bf").to_vec());
+    let mut vm = Interpreter::new();
+    vm.set_code(program);
+    vm.run();
+    rle::fold(&mut vm.matrix).unwrap();
+    // Claim the program printed something else
+    vm.matrix.output_matrix[0] = Fq::from(b'J' as u64);
+
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&vm.matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(vm.matrix, k).unwrap();
+    let prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn test_wrong_public_output() {
+    let program = code::compile(include_bytes!("../ckb-bf-zkvm/res/hello_world.bf").to_vec());
+    let mut vm = Interpreter::new();
+    vm.set_code(program);
+    vm.run();
+    rle::fold(&mut vm.matrix).unwrap();
+
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&vm.matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(vm.matrix, k).unwrap();
+    // The verifier expects a different output than the one proven
+    let mut instances = circuit.instances();
+    instances[2][1] = Fr::from(b'J' as u64);
+    let prover = MockProver::run(k, &circuit, instances).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn test_invalid_opcode() {
+    let program = code::compile(include_bytes!("../ckb-bf-zkvm/res/hello_world.bf").to_vec());
+    let mut vm = Interpreter::new();
+    vm.set_code(program);
+    vm.run();
+    rle::fold(&mut vm.matrix).unwrap();
+    // 1 is not an opcode, the opcode lookup rejects it
+    vm.matrix
+        .processor_matrix
+        .last_mut()
+        .unwrap()
+        .current_instruction = Fq::from(1);
+
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&vm.matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(vm.matrix, k).unwrap();
+    let prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
+    assert!(prover.verify().is_err());
+}
+
 #[test]
 fn test_prove_wrapping() {
     let program = code::compile(include_bytes!("../wrapping_op.b").to_vec());
     let mut vm = Interpreter::new();
     vm.set_code(program);
     vm.run();
+    rle::fold(&mut vm.matrix).unwrap();
 
-    let circuit = MyCircuit::<Fq, { DOMAIN }>::new(vm.matrix);
-    let prover = MockProver::run(10, &circuit, vec![]).unwrap();
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&vm.matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(vm.matrix, k).unwrap();
+    let prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
     prover.assert_satisfied();
 }
 
@@ -50,8 +144,156 @@ fn test_prove_pearson() {
     vm.set_code(program);
     vm.set_input(code::easygen("a"));
     vm.run();
+    rle::fold(&mut vm.matrix).unwrap();
+
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&vm.matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(vm.matrix, k).unwrap();
+    let prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn test_prove_folded_layout() {
+    // About 1300 rows, 4 column groups need a quarter of them
+    let program = code::compile(b"+++++++++++++++[>++++++++++++++++[>+++<-]<-]".to_vec());
+    let mut vm = Interpreter::new();
+    vm.set_code(program);
+    vm.run();
+    rle::fold(&mut vm.matrix).unwrap();
 
-    let circuit = MyCircuit::<Fq, {DOMAIN}>::new(vm.matrix);
-    let prover = MockProver::run(21, &circuit, vec![]).unwrap();
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&vm.matrix).unwrap();
+    let folded_k = MyCircuit::<Fr, { DOMAIN }, 4>::min_k(&vm.matrix).unwrap();
+    assert!(folded_k < k);
+    let circuit = MyCircuit::<Fr, { DOMAIN }, 4>::new(vm.matrix, folded_k).unwrap();
+    let prover = MockProver::run(folded_k, &circuit, circuit.instances()).unwrap();
     prover.assert_satisfied();
 }
+
+#[test]
+fn test_k_too_large() {
+    let source = include_bytes!("../ckb-bf-zkvm/res/hello_world.bf");
+    let matrix = vm::run(source, &[], None).unwrap();
+    let result = MyCircuit::<Fr, { DOMAIN }>::new(matrix, MAX_K + 1);
+    assert!(matches!(
+        result,
+        Err(Error::CircuitTooLarge { max_k: MAX_K, .. })
+    ));
+}
+
+#[test]
+fn test_k_too_small() {
+    let source = include_bytes!("../ckb-bf-zkvm/res/hello_world.bf");
+    let matrix = vm::run(source, &[], None).unwrap();
+    let min_k = MyCircuit::<Fr, { DOMAIN }>::min_k(&matrix).unwrap();
+    let result = MyCircuit::<Fr, { DOMAIN }>::new(matrix, min_k - 1);
+    // The error reports the k the trace needs, not MAX_K
+    assert!(matches!(
+        result,
+        Err(Error::TraceDoesNotFit { k, min_k: needed, .. }) if k == min_k - 1 && needed == min_k
+    ));
+}
+
+#[test]
+fn test_empty_trace() {
+    let result = MyCircuit::<Fr, { DOMAIN }>::new(Matrix::default(), 10);
+    assert!(matches!(result, Err(Error::Compile { .. })));
+}
+
+#[test]
+fn test_truncated_trace() {
+    // About 1300 rows, cut at a row that does not halt
+    let source = b"+++++++++++++++[>++++++++++++++++[>+++<-]<-]";
+    let mut matrix = vm::run(source, &[], None).unwrap();
+    let k = 9;
+    let rows = MyCircuit::<Fr, { DOMAIN }>::usable_rows(k);
+    assert!(matrix.processor_matrix.len() > rows);
+    matrix.processor_matrix.truncate(rows);
+    // The memory table of the remaining rows, sorted by (mp, clk)
+    let template = matrix.memory_matrix[0].clone();
+    matrix.memory_matrix = matrix
+        .processor_matrix
+        .iter()
+        .map(|reg| {
+            let mut row = template.clone();
+            row.cycle = reg.cycle;
+            row.memory_pointer = reg.memory_pointer;
+            row.memory_value = reg.memory_value;
+            row
+        })
+        .collect();
+    matrix
+        .memory_matrix
+        .sort_by_key(|row| (to_u64(row.memory_pointer), to_u64(row.cycle)));
+
+    // The trace fills every row, so there is no halting row to pad it with
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(matrix, k).unwrap();
+    let prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
+    let failures = prover.verify().unwrap_err();
+    assert!(failures
+        .iter()
+        .any(|failure| failure.to_string().contains("B9")));
+}
+
+#[test]
+fn test_forged_memory_order() {
+    // Cell 1 holds 0 at clk 1, 1 at clk 2 and 4, and is printed at clk 4
+    let mut matrix = vm::run(b">+><.<", &[], None).unwrap();
+    assert_eq!(to_bytes(&matrix.output_matrix), vec![1]);
+    // Claim that cell 1 holds 0 at clk 4 and 5 and move these reads before the write at clk 2.
+    // Within the cell mv only changes between clk 1 and 2, so M1 and M2 still hold.
+    for reg in &mut matrix.processor_matrix {
+        if [4, 5].contains(&to_u64(reg.cycle)) {
+            reg.memory_value = Fq::from(0);
+        }
+    }
+    for row in &mut matrix.memory_matrix {
+        if to_u64(row.memory_pointer) == 1 && [4, 5].contains(&to_u64(row.cycle)) {
+            row.memory_value = Fq::from(0);
+        }
+    }
+    matrix.memory_matrix.sort_by_key(|row| {
+        let clk = to_u64(row.cycle);
+        (to_u64(row.memory_pointer), clk < 4, clk)
+    });
+    matrix.output_matrix[0] = Fq::from(0);
+
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(matrix, k).unwrap();
+    let prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
+    let failures = prover.verify().unwrap_err();
+    assert!(failures
+        .iter()
+        .any(|failure| failure.to_string().contains("M5")));
+}
+
+#[test]
+fn test_forged_memory_start() {
+    let failures = |forge: fn(&mut Matrix)| {
+        let mut matrix = vm::run(b"+.", &[], None).unwrap();
+        forge(&mut matrix);
+        let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&matrix).unwrap();
+        let circuit = MyCircuit::<Fr, { DOMAIN }>::new(matrix, k).unwrap();
+        let prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
+        prover
+            .verify()
+            .unwrap_err()
+            .iter()
+            .map(|failure| failure.to_string())
+            .collect::<Vec<_>>()
+    };
+    let fails = |failures: Vec<String>, gate: &str| failures.iter().any(|f| f.contains(gate));
+
+    // The first row of the memory table is the first access of cell 0 at clk 0, with mv 0
+    assert!(fails(
+        failures(|matrix| matrix.memory_matrix[0].cycle = Fq::from(3)),
+        "MB0"
+    ));
+    assert!(fails(
+        failures(|matrix| matrix.memory_matrix[0].memory_pointer = Fq::from(1)),
+        "MB1"
+    ));
+    assert!(fails(
+        failures(|matrix| matrix.memory_matrix[0].memory_value = Fq::from(1)),
+        "MB2"
+    ));
+}
@@ -0,0 +1,118 @@
+use ckb_bf_zkvm::code;
+use ckb_bf_zkvm::interpreter::Interpreter;
+use halo2_bf::bundle::{ProofBundle, BUNDLE_MAGIC};
+use halo2_bf::main_config::MyCircuit;
+use halo2_bf::prover::{self, TranscriptKind};
+use halo2_bf::rle;
+use halo2_bf::utils::{to_bytes, DOMAIN};
+use halo2_bf::vm;
+use halo2_proofs::halo2curves::bn256::Fr;
+
+fn bundle() -> ProofBundle {
+    let program = [Fr::from(43), Fr::from(46)];
+    ProofBundle::new::<_, { DOMAIN }>(
+        10,
+        TranscriptKind::Keccak256,
+        &program,
+        b"in".to_vec(),
+        b"out".to_vec(),
+        vec![7; 64],
+    )
+}
+
+#[test]
+fn test_binary_roundtrip() {
+    let bundle = bundle();
+    let mut bytes = Vec::new();
+    bundle.write(&mut bytes).unwrap();
+    assert!(bytes.starts_with(&BUNDLE_MAGIC));
+    assert_eq!(ProofBundle::read(&mut bytes.as_slice()).unwrap(), bundle);
+    assert_eq!(ProofBundle::from_slice(&bytes).unwrap(), bundle);
+
+    // Truncated bundles are rejected
+    assert!(ProofBundle::read(&mut &bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn test_json_roundtrip() {
+    let bundle = bundle();
+    let mut bytes = Vec::new();
+    bundle.write_json(&mut bytes).unwrap();
+    assert_eq!(
+        ProofBundle::read_json(&mut bytes.as_slice()).unwrap(),
+        bundle
+    );
+    assert_eq!(ProofBundle::from_slice(&bytes).unwrap(), bundle);
+}
+
+#[test]
+fn test_unknown_version() {
+    let mut bundle = bundle();
+    bundle.version += 1;
+    let mut bytes = Vec::new();
+    bundle.write(&mut bytes).unwrap();
+    assert!(ProofBundle::read(&mut bytes.as_slice()).is_err());
+}
+
+#[test]
+fn test_other_program() {
+    let bundle = bundle();
+    assert!(bundle
+        .instances::<_, { DOMAIN }>(vec![Fr::from(43), Fr::from(46)])
+        .is_ok());
+    assert!(bundle
+        .instances::<_, { DOMAIN }>(vec![Fr::from(43), Fr::from(43)])
+        .is_err());
+    assert!(bundle
+        .instances::<_, 16>(vec![Fr::from(43), Fr::from(46)])
+        .is_err());
+}
+
+#[test]
+fn test_verify_bundle() {
+    let source = include_bytes!("../ckb-bf-zkvm/res/neptune_tutorial.bf").to_vec();
+    let mut vm = Interpreter::new();
+    vm.set_code(code::compile(source.clone()));
+    vm.set_input(code::easygen("a"));
+    vm.run();
+    rle::fold(&mut vm.matrix).unwrap();
+
+    let input = to_bytes(&vm.matrix.input_matrix);
+    let output = to_bytes(&vm.matrix.output_matrix);
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&vm.matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(vm.matrix, k).unwrap();
+    let program = circuit.instances()[0].clone();
+    let params = prover::setup(k);
+    let pk = prover::keygen(&params, &circuit).unwrap();
+    let transcript = TranscriptKind::Keccak256;
+    let proof = prover::prove_with(&params, &pk, circuit, transcript).unwrap();
+    let mut bytes = Vec::new();
+    ProofBundle::new::<_, { DOMAIN }>(k, transcript, &program, input, output, proof)
+        .write(&mut bytes)
+        .unwrap();
+
+    // A separate process only has the bundle, the program and the keys
+    let bundle = ProofBundle::from_slice(&bytes).unwrap();
+    assert_eq!(bundle.transcript, transcript);
+    let words = vm::compile(&source).unwrap();
+    let instances = bundle.instances::<_, { DOMAIN }>(words).unwrap();
+    prover::verify_with(
+        &params,
+        pk.get_vk(),
+        &bundle.proof,
+        &instances,
+        bundle.transcript,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_unknown_transcript() {
+    let mut bytes = Vec::new();
+    bundle().write(&mut bytes).unwrap();
+    // The tag follows the magic, the versions, k and RANGE
+    let tag = BUNDLE_MAGIC.len() + 4 + 4 + 4 + 8;
+    assert_eq!(bytes[tag], TranscriptKind::Keccak256.tag());
+    bytes[tag] = 3;
+    assert!(ProofBundle::read(&mut bytes.as_slice()).is_err());
+}
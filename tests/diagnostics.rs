@@ -0,0 +1,44 @@
+use halo2_bf::diagnostics::{self, SourceLocation};
+use halo2_bf::main_config::MyCircuit;
+use halo2_bf::utils::DOMAIN;
+use halo2_bf::{prover, vm, Error};
+use halo2_proofs::halo2curves::bn256::{Fq, Fr};
+
+#[test]
+fn test_diagnose_processor_row() {
+    let source = include_bytes!("../ckb-bf-zkvm/res/hello_world.bf");
+    let mut matrix = vm::run(source, &[], None).unwrap();
+    // mv does not follow the instruction executed at row 3
+    matrix.processor_matrix[3].memory_value = Fq::from(7);
+
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(matrix, k).unwrap();
+    let failures = match prover::mock(&circuit) {
+        Err(Error::Unsatisfied(failures)) => failures,
+        _ => panic!("the tampered trace satisfies the constraints"),
+    };
+    let diagnostics = diagnostics::diagnose(&circuit, source, &failures);
+    assert_eq!(diagnostics.len(), failures.len());
+
+    let ip = circuit.matrix().processor_matrix[3].instruction_pointer;
+    let diagnostic = diagnostics
+        .iter()
+        .find(|d| d.check.starts_with("P_") && d.processor_row == Some(3))
+        .expect("no processor gate fails on row 3");
+    assert_eq!(diagnostic.table.as_deref(), Some("Load Processor Table"));
+    assert_eq!(diagnostic.cycle, Some(3));
+    assert_eq!(Fq::from(diagnostic.ip.unwrap() as u64), ip);
+    let position = vm::positions(source)[diagnostic.ip.unwrap()];
+    assert_eq!(
+        diagnostic.source.as_ref().map(|s| s.position),
+        Some(position)
+    );
+}
+
+#[test]
+fn test_source_location() {
+    let location = SourceLocation::new(b"++\n>[-]<\n.", 5);
+    assert_eq!((location.line, location.column), (2, 3));
+    assert_eq!(location.snippet, ">[-]<");
+    assert_eq!(location.caret, 2);
+}
@@ -0,0 +1,108 @@
+use ckb_bf_zkvm::code;
+use ckb_bf_zkvm::interpreter::Interpreter;
+use halo2_bf::keystore::KeyStore;
+use halo2_bf::main_config::MyCircuit;
+use halo2_bf::prover;
+use halo2_bf::rle;
+use halo2_bf::utils::DOMAIN;
+use halo2_bf::Error;
+use halo2_proofs::halo2curves::bn256::Fr;
+use std::path::PathBuf;
+
+fn neptune() -> (MyCircuit<Fr, { DOMAIN }>, u32) {
+    let program = code::compile(include_bytes!("../ckb-bf-zkvm/res/neptune_tutorial.bf").to_vec());
+    let mut vm = Interpreter::new();
+    vm.set_code(program);
+    vm.set_input(code::easygen("a"));
+    vm.run();
+    rle::fold(&mut vm.matrix).unwrap();
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&vm.matrix).unwrap();
+    (MyCircuit::new(vm.matrix, k).unwrap(), k)
+}
+
+fn store_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("halo2_bf_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn test_reload_keys() {
+    let dir = store_dir("reload");
+    let store = KeyStore::new(&dir);
+    let (circuit, k) = neptune();
+    assert!(!store.contains::<{ DOMAIN }>(k));
+    store.load_or_generate(&circuit).unwrap();
+    assert!(store.contains::<{ DOMAIN }>(k));
+
+    // A proof made with the reloaded keys verifies with the reloaded verifying key
+    let instances = circuit.instances();
+    let (params, pk) = store.load_or_generate(&circuit).unwrap();
+    let proof = prover::prove(&params, &pk, circuit).unwrap();
+    let (params, vk) = store.load_verifier::<{ DOMAIN }>(k).unwrap();
+    prover::verify(&params, &vk, &proof, &instances).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_refuse_mismatched_keys() {
+    let dir = store_dir("mismatch");
+    let store = KeyStore::new(&dir);
+    let (circuit, k) = neptune();
+    store.load_or_generate(&circuit).unwrap();
+
+    // Keys of another k moved into this directory
+    let manifest = store.path::<{ DOMAIN }>(k).join("manifest.json");
+    let tampered = std::fs::read_to_string(&manifest)
+        .unwrap()
+        .replace(&format!("\"k\": {}", k), &format!("\"k\": {}", k + 1));
+    std::fs::write(&manifest, tampered).unwrap();
+    assert!(matches!(
+        store.load_verifier::<{ DOMAIN }>(k),
+        Err(Error::KeyMismatch { .. })
+    ));
+
+    // Nothing stored for another RANGE
+    assert!(matches!(store.load_verifier::<16>(k), Err(Error::Io(_))));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_load_stored_proving_key() {
+    let dir = store_dir("pk");
+    let store = KeyStore::new(&dir);
+    let (circuit, k) = neptune();
+    store.load_or_generate(&circuit).unwrap();
+    let path = store.path::<{ DOMAIN }>(k);
+    assert!(path.join("pk.bin").exists());
+
+    // The proving key is read from pk.bin, no circuit is needed to load it
+    let instances = circuit.instances();
+    let (params, pk) = store.load_prover::<{ DOMAIN }>(k).unwrap();
+    let proof = prover::prove(&params, &pk, circuit).unwrap();
+    prover::verify(&params, pk.get_vk(), &proof, &instances).unwrap();
+
+    // Without pk.bin there is nothing to load, the key is not generated again
+    std::fs::remove_file(path.join("pk.bin")).unwrap();
+    assert!(matches!(
+        store.load_prover::<{ DOMAIN }>(k),
+        Err(Error::Io(_))
+    ));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_generate_without_program() {
+    let dir = store_dir("generate");
+    let store = KeyStore::new(&dir);
+    let (circuit, k) = neptune();
+    store.generate::<{ DOMAIN }>(k).unwrap();
+
+    // Keys generated without a program prove any program that fits
+    let instances = circuit.instances();
+    let (params, pk) = store.load_prover::<{ DOMAIN }>(k).unwrap();
+    let proof = prover::prove(&params, &pk, circuit).unwrap();
+    let (params, vk) = store.load_verifier::<{ DOMAIN }>(k).unwrap();
+    prover::verify(&params, &vk, &proof, &instances).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,263 @@
+use halo2_bf::main_config::MyCircuit;
+use halo2_bf::prover::aggregation::{self, AggregationCircuit};
+use halo2_bf::prover::{self, evm, TranscriptKind};
+use halo2_bf::utils::DOMAIN;
+use halo2_bf::vm;
+use halo2_proofs::halo2curves::bn256::Fr;
+use halo2_proofs::halo2curves::pasta::Fp;
+
+#[test]
+fn test_kzg_hello_world() {
+    let matrix = vm::run(
+        include_bytes!("../ckb-bf-zkvm/res/hello_world.bf"),
+        b"",
+        None,
+    )
+    .unwrap();
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(matrix, k).unwrap();
+    let instances = circuit.instances();
+    let params = prover::setup(k);
+    let pk = prover::keygen(&params, &circuit).unwrap();
+    let proof = prover::prove(&params, &pk, circuit).unwrap();
+    prover::verify(&params, pk.get_vk(), &proof, &instances).unwrap();
+
+    // The proof does not verify against a different output
+    let mut wrong_instances = instances;
+    wrong_instances[2][1] = Fr::from(b'J' as u64);
+    assert!(prover::verify(&params, pk.get_vk(), &proof, &wrong_instances).is_err());
+}
+
+#[test]
+fn test_transcripts() {
+    let source = include_bytes!("../ckb-bf-zkvm/res/hello_world.bf");
+    let circuit = || {
+        let matrix = vm::run(source, b"", None).unwrap();
+        let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&matrix).unwrap();
+        MyCircuit::<Fr, { DOMAIN }>::new(matrix, k).unwrap()
+    };
+    let k = circuit().k();
+    let instances = circuit().instances();
+    let params = prover::setup(k);
+    let pk = prover::keygen(&params, &circuit()).unwrap();
+    let transcripts = [
+        TranscriptKind::Blake2b,
+        TranscriptKind::Keccak256,
+        TranscriptKind::Poseidon,
+    ];
+    for transcript in transcripts {
+        let proof = prover::prove_with(&params, &pk, circuit(), transcript).unwrap();
+        // The proof only verifies with the transcript it was created with
+        for other in transcripts {
+            let result = prover::verify_with(&params, pk.get_vk(), &proof, &instances, other);
+            assert_eq!(result.is_ok(), other == transcript);
+        }
+    }
+}
+
+#[test]
+fn test_ipa_hello_world() {
+    let matrix = vm::run(
+        include_bytes!("../ckb-bf-zkvm/res/hello_world.bf"),
+        b"",
+        None,
+    )
+    .unwrap();
+    let k = MyCircuit::<Fp, { DOMAIN }>::min_k(&matrix).unwrap();
+    let circuit = MyCircuit::<Fp, { DOMAIN }>::new(matrix, k).unwrap();
+    let instances = circuit.instances();
+    let params = prover::ipa::setup(k);
+    let pk = prover::ipa::keygen(&params, &circuit).unwrap();
+    let proof = prover::ipa::prove(&params, &pk, circuit).unwrap();
+    prover::ipa::verify(&params, pk.get_vk(), &proof, &instances).unwrap();
+
+    // The proof does not verify against a different output
+    let mut wrong_instances = instances.clone();
+    wrong_instances[2][1] = Fp::from(b'J' as u64);
+    assert!(prover::ipa::verify(&params, pk.get_vk(), &proof, &wrong_instances).is_err());
+
+    // Nor with another transcript than the one it was created with
+    let circuit = MyCircuit::<Fp, { DOMAIN }>::new(
+        vm::run(
+            include_bytes!("../ckb-bf-zkvm/res/hello_world.bf"),
+            b"",
+            None,
+        )
+        .unwrap(),
+        k,
+    )
+    .unwrap();
+    let transcript = TranscriptKind::Keccak256;
+    let proof = prover::ipa::prove_with(&params, &pk, circuit, transcript).unwrap();
+    prover::ipa::verify_with(&params, pk.get_vk(), &proof, &instances, transcript).unwrap();
+    assert!(prover::ipa::verify(&params, pk.get_vk(), &proof, &instances).is_err());
+}
+
+#[test]
+fn test_vk_depends_only_on_k() {
+    let run = |source: &[u8], input: &[u8]| vm::run(source, input, None).unwrap();
+    let hello_world = run(include_bytes!("../ckb-bf-zkvm/res/hello_world.bf"), b"");
+    let neptune = run(
+        include_bytes!("../ckb-bf-zkvm/res/neptune_tutorial.bf"),
+        b"a",
+    );
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&hello_world)
+        .unwrap()
+        .max(MyCircuit::<Fr, { DOMAIN }>::min_k(&neptune).unwrap());
+
+    let params = prover::setup(k);
+    let hello_world = prover::keygen(
+        &params,
+        &MyCircuit::<Fr, { DOMAIN }>::new(hello_world, k).unwrap(),
+    )
+    .unwrap();
+    let neptune = prover::keygen(
+        &params,
+        &MyCircuit::<Fr, { DOMAIN }>::new(neptune, k).unwrap(),
+    )
+    .unwrap();
+
+    // Different programs and trace lengths, same selectors and copy constraints
+    assert_eq!(
+        hello_world.get_vk().fixed_commitments(),
+        neptune.get_vk().fixed_commitments()
+    );
+    assert_eq!(
+        hello_world.get_vk().permutation().commitments(),
+        neptune.get_vk().permutation().commitments()
+    );
+}
+
+#[test]
+fn test_verify_accumulated() {
+    let run = |source: &[u8], input: &[u8]| vm::run(source, input, None).unwrap();
+    let matrices = [
+        run(include_bytes!("../ckb-bf-zkvm/res/hello_world.bf"), b""),
+        run(
+            include_bytes!("../ckb-bf-zkvm/res/neptune_tutorial.bf"),
+            b"a",
+        ),
+        run(include_bytes!("../wrapping_op.b"), b""),
+    ];
+    let k = matrices
+        .iter()
+        .map(|matrix| MyCircuit::<Fr, { DOMAIN }>::min_k(matrix).unwrap())
+        .max()
+        .unwrap();
+
+    let params = prover::setup(k);
+    let circuits = matrices
+        .map(|matrix| MyCircuit::<Fr, { DOMAIN }>::new(matrix, k).unwrap())
+        .to_vec();
+    // The keys only depend on k, so one pair of keys serves every program
+    let pk = prover::keygen(&params, &circuits[0]).unwrap();
+    let instances = circuits
+        .iter()
+        .map(|circuit| circuit.instances())
+        .collect::<Vec<_>>();
+    let proofs = circuits
+        .into_iter()
+        .map(|circuit| prover::prove(&params, &pk, circuit).unwrap())
+        .collect::<Vec<_>>();
+    prover::verify_accumulated(
+        &params,
+        pk.get_vk(),
+        &proofs,
+        &instances,
+        TranscriptKind::Blake2b,
+    )
+    .unwrap();
+
+    // A single wrong statement fails the whole batch
+    let mut wrong_instances = instances;
+    wrong_instances[0][2][1] = Fr::from(b'J' as u64);
+    assert!(prover::verify_accumulated(
+        &params,
+        pk.get_vk(),
+        &proofs,
+        &wrong_instances,
+        TranscriptKind::Blake2b
+    )
+    .is_err());
+}
+
+#[test]
+fn test_evm_verifier() {
+    let matrix = vm::run(
+        include_bytes!("../ckb-bf-zkvm/res/hello_world.bf"),
+        b"",
+        None,
+    )
+    .unwrap();
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(matrix, k).unwrap();
+    let instances = circuit.instances();
+    let params = prover::setup(k);
+    let pk = prover::keygen(&params, &circuit).unwrap();
+    let num_instance = instances.iter().map(Vec::len).collect();
+    let yul = evm::verifier_yul(&params, pk.get_vk(), num_instance).unwrap();
+    let bytecode = evm::verifier_bytecode(&yul).unwrap();
+    let proof = evm::prove(&params, &pk, circuit).unwrap();
+    evm::verify(bytecode.clone(), &instances, &proof).unwrap();
+
+    // The contract rejects a different output
+    let mut wrong_instances = instances;
+    wrong_instances[2][1] = Fr::from(b'J' as u64);
+    assert!(evm::verify(bytecode, &wrong_instances, &proof).is_err());
+}
+
+#[test]
+fn test_aggregate() {
+    let run = |source: &[u8], input: &[u8]| vm::run(source, input, None).unwrap();
+    let matrices = [
+        run(include_bytes!("../ckb-bf-zkvm/res/hello_world.bf"), b""),
+        run(
+            include_bytes!("../ckb-bf-zkvm/res/neptune_tutorial.bf"),
+            b"a",
+        ),
+        run(include_bytes!("../wrapping_op.b"), b""),
+    ];
+    let k = matrices
+        .iter()
+        .map(|matrix| MyCircuit::<Fr, { DOMAIN }>::min_k(matrix).unwrap())
+        .max()
+        .unwrap();
+
+    // The verifiers of three proofs fit into 2^22 rows
+    let params = prover::setup(22);
+    let inner = aggregation::downsize(&params, k);
+    let circuits = matrices
+        .map(|matrix| MyCircuit::<Fr, { DOMAIN }>::new(matrix, k).unwrap())
+        .to_vec();
+    let pk = prover::keygen(&inner, &circuits[0]).unwrap();
+    let snarks = circuits
+        .into_iter()
+        .map(|circuit| aggregation::prove(&inner, &pk, circuit).unwrap())
+        .collect::<Vec<_>>();
+
+    let circuit = AggregationCircuit::new(&params, snarks.clone()).unwrap();
+    let instances = circuit.instances();
+    // The statements of the proofs follow the accumulator
+    let statements = snarks
+        .iter()
+        .flat_map(|snark| snark.instances.concat())
+        .collect::<Vec<_>>();
+    assert_eq!(instances[0][16..], statements[..]);
+    let aggregation_pk = prover::keygen(&params, &circuit).unwrap();
+    let proof = aggregation::prove_aggregation(&params, &aggregation_pk, circuit).unwrap();
+    aggregation::verify(&params, aggregation_pk.get_vk(), &proof, &instances).unwrap();
+
+    // The aggregated proof does not verify against a different statement
+    let mut wrong_instances = instances;
+    let last = wrong_instances[0].len() - 1;
+    wrong_instances[0][last] = Fr::from(b'J' as u64);
+    assert!(
+        aggregation::verify(&params, aggregation_pk.get_vk(), &proof, &wrong_instances).is_err()
+    );
+
+    // A proof of a wrong statement gives an accumulator that fails the pairing check
+    let mut snarks = snarks;
+    snarks[0].instances[2][1] = Fr::from(b'J' as u64);
+    let circuit = AggregationCircuit::new(&params, snarks).unwrap();
+    assert!(aggregation::decide(&params, &circuit.instances()).is_err());
+}
@@ -0,0 +1,98 @@
+use ckb_bf_zkvm::code;
+use ckb_bf_zkvm::interpreter::Interpreter;
+use ckb_bf_zkvm::matrix::Matrix;
+use halo2_bf::main_config::MyCircuit;
+use halo2_bf::utils::{to_bytes, CLEAR, DOMAIN, LB, MOVE, OPCODES};
+use halo2_bf::{prover, rle, vm, Error};
+use halo2_proofs::halo2curves::bn256::Fr;
+
+#[test]
+fn test_fold_program() {
+    let words = |words: &[u64]| words.iter().map(|w| Fr::from(*w)).collect::<Vec<_>>();
+    // Brackets keep their jump targets, which move with the folded words
+    assert_eq!(
+        vm::compile::<Fr>(b"+[>.<-]").unwrap(),
+        words(&[43, 1, 91, 13, 62, 1, 46, 60, 1, 45, 1, 93, 4])
+    );
+    // Runs longer than MAX_RUN are split
+    assert_eq!(
+        vm::compile::<Fr>("+".repeat(300).as_bytes()).unwrap(),
+        words(&[43, 255, 43, 45])
+    );
+}
+
+#[test]
+fn test_fold_macros() {
+    let words = |words: &[u8]| {
+        words
+            .iter()
+            .map(|w| Fr::from(*w as u64))
+            .collect::<Vec<_>>()
+    };
+    let (lb, clear, mv) = (OPCODES[LB], OPCODES[CLEAR], OPCODES[MOVE]);
+    assert_eq!(
+        vm::compile::<Fr>(b"+++>>[-]").unwrap(),
+        words(&[b'+', 3, b'>', 2, clear])
+    );
+    assert_eq!(vm::compile::<Fr>(b"[+]").unwrap(), words(&[clear]));
+    assert_eq!(
+        vm::compile::<Fr>(b"[>>+<<-]").unwrap(),
+        words(&[lb, 8, clear, b'>', 2, mv, b'<', 2])
+    );
+    // Copy to two cells, in order of offset
+    assert_eq!(
+        vm::compile::<Fr>(b"[->+<<+>]").unwrap(),
+        words(&[lb, 11, clear, b'<', 1, mv, b'>', 2, mv, b'<', 1])
+    );
+    // Not a move: the target receives twice the value
+    assert_eq!(vm::compile::<Fr>(b"[->++<]").unwrap().len(), 12);
+
+    // The macros compute what the loops compute
+    let source = b"++++++[->+++++++>+<<]>[->+<]>[-<+>]<.>+[-]+[<+>-]<.";
+    let matrix = vm::run(source, &[], None).unwrap();
+    assert_eq!(to_bytes(&matrix.output_matrix), b"01".to_vec());
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(matrix, k).unwrap();
+    prover::mock(&circuit).unwrap();
+}
+
+#[test]
+fn test_fold_macros_on_zero_cells() {
+    // Both loops are skipped, the macros must not shift below cell 0 either
+    let source = b"[-<+>]>[->+<<<+>>]+.";
+    let matrix = vm::run(source, &[], None).unwrap();
+    assert_eq!(to_bytes(&matrix.output_matrix), vec![1]);
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(matrix, k).unwrap();
+    prover::mock(&circuit).unwrap();
+}
+
+#[test]
+fn test_fold_trace() {
+    let source = include_bytes!("../wrapping_op.b");
+    let mut unfolded = Interpreter::new();
+    unfolded.set_code(code::compile(source.to_vec()));
+    unfolded.run();
+    let matrix = vm::run(source, &[], None).unwrap();
+    assert!(matrix.processor_matrix.len() < unfolded.matrix.processor_matrix.len());
+    assert_eq!(matrix.output_matrix, unfolded.matrix.output_matrix);
+    // Folding the trace of the interpreter gives the same trace
+    rle::fold(&mut unfolded.matrix).unwrap();
+    assert_eq!(
+        unfolded.matrix.processor_matrix.len(),
+        matrix.processor_matrix.len()
+    );
+    assert_eq!(unfolded.matrix.output_matrix, matrix.output_matrix);
+
+    // The folded trace proves the folded program
+    let k = MyCircuit::<Fr, { DOMAIN }>::min_k(&matrix).unwrap();
+    let circuit = MyCircuit::<Fr, { DOMAIN }>::new(matrix, k).unwrap();
+    assert_eq!(circuit.instances()[0], vm::compile::<Fr>(source).unwrap());
+    prover::mock(&circuit).unwrap();
+}
+
+#[test]
+fn test_fold_empty_trace() {
+    let result = rle::fold(&mut Matrix::default());
+    assert!(matches!(result, Err(Error::Compile { .. })));
+}
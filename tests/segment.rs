@@ -0,0 +1,101 @@
+use ckb_bf_zkvm::matrix::Matrix;
+use halo2_bf::prover::{self, TranscriptKind};
+use halo2_bf::segment::{verify_chain, Boundary, SegmentCircuit};
+use halo2_bf::utils::DOMAIN;
+use halo2_bf::{vm, Error};
+use halo2_proofs::dev::MockProver;
+use halo2_proofs::halo2curves::bn256::Fr;
+
+// About 1300 rows, reads a byte first and prints at the end
+const SOURCE: &[u8] = b",>+++++++++++++++[>++++++++++++++++[>+++<-]<-]>>.<<<.";
+const K: u32 = 9;
+
+#[test]
+fn test_mock_segments() {
+    let matrix = vm::run(SOURCE, b"a", None).unwrap();
+    let segments = SegmentCircuit::<Fr, { DOMAIN }>::split(&matrix, K).unwrap();
+    assert!(segments.len() > 2);
+    assert_eq!(segments[0].start(), &Boundary::default());
+    for pair in segments.windows(2) {
+        assert_eq!(pair[0].end(), pair[1].start());
+    }
+    let last = segments.last().unwrap().end();
+    assert_eq!(last.input_offset, 1);
+    assert_eq!(last.output_offset, 2);
+    assert_eq!(last.memory, vec![b'a', 0, 0, 208]);
+
+    for segment in &segments {
+        let prover = MockProver::run(K, segment, segment.instances()).unwrap();
+        prover.assert_satisfied();
+    }
+}
+
+#[test]
+fn test_forged_boundary() {
+    let matrix = vm::run(SOURCE, b"a", None).unwrap();
+    let segments = SegmentCircuit::<Fr, { DOMAIN }>::split(&matrix, K).unwrap();
+    let segment = &segments[1];
+
+    // The memory table of the segment reads the counter, which can't start at another value
+    let mut instances = segment.instances();
+    instances[3][1] += Fr::from(1);
+    let prover = MockProver::run(K, segment, instances).unwrap();
+    assert!(prover.verify().is_err());
+
+    // Nor can the segment end at another ip
+    let mut instances = segment.instances();
+    instances[5][5] += Fr::from(1);
+    let prover = MockProver::run(K, segment, instances).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn test_verify_chain() {
+    let matrix = vm::run(SOURCE, b"a", None).unwrap();
+    let program = vm::compile::<Fr>(SOURCE).unwrap();
+    let segments = SegmentCircuit::<Fr, { DOMAIN }>::split(&matrix, K).unwrap();
+
+    let params = prover::setup(K);
+    // The keys only depend on k, so one pair of keys serves every segment
+    let pk = prover::keygen(&params, &segments[0]).unwrap();
+    let proofs = segments
+        .into_iter()
+        .map(|segment| {
+            prover::prove_segment(&params, &pk, segment, TranscriptKind::Keccak256).unwrap()
+        })
+        .collect::<Vec<_>>();
+    let output = [208, b'a'];
+    verify_chain(&params, pk.get_vk(), &program, b"a", &output, &proofs).unwrap();
+
+    // The segments must print the whole output
+    let result = verify_chain(&params, pk.get_vk(), &program, b"a", &output[..1], &proofs);
+    assert!(matches!(result, Err(Error::InvalidChain(_))));
+
+    // A segment can't be left out
+    let mut skipped = proofs.clone();
+    skipped.remove(1);
+    let result = verify_chain(&params, pk.get_vk(), &program, b"a", &output, &skipped);
+    assert!(matches!(result, Err(Error::InvalidChain(_))));
+
+    // The proofs are checked with the transcript they were created with
+    let mut mixed = proofs.clone();
+    mixed[1].transcript = TranscriptKind::Blake2b;
+    let result = verify_chain(&params, pk.get_vk(), &program, b"a", &output, &mixed);
+    assert!(matches!(result, Err(Error::InvalidChain(_))));
+    for segment in &mut mixed {
+        segment.transcript = TranscriptKind::Blake2b;
+    }
+    assert!(verify_chain(&params, pk.get_vk(), &program, b"a", &output, &mixed).is_err());
+
+    // Forging the memory between two segments breaks their proofs
+    let mut forged = proofs;
+    forged[0].end.memory[1] += 1;
+    forged[1].start.memory[1] += 1;
+    assert!(verify_chain(&params, pk.get_vk(), &program, b"a", &output, &forged).is_err());
+}
+
+#[test]
+fn test_split_empty_trace() {
+    let result = SegmentCircuit::<Fr, { DOMAIN }>::split(&Matrix::default(), K);
+    assert!(matches!(result, Err(Error::InvalidChain(_))));
+}
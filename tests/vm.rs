@@ -0,0 +1,41 @@
+use halo2_bf::vm;
+use halo2_bf::Error;
+
+#[test]
+fn test_unbalanced_brackets() {
+    assert!(matches!(
+        vm::check(b"+[-]]"),
+        Err(Error::Compile { position: 4, .. })
+    ));
+    assert!(matches!(
+        vm::run(b"+[[-]", &[], None),
+        Err(Error::Compile { position: 1, .. })
+    ));
+}
+
+#[test]
+fn test_step_limit() {
+    let program = include_bytes!("../ckb-bf-zkvm/res/hello_world.bf");
+    assert!(matches!(
+        vm::run(program, &[], Some(10)),
+        Err(Error::StepLimit { limit: 10 })
+    ));
+    assert!(vm::run(program, &[], None).is_ok());
+}
+
+#[test]
+fn test_step_limit_without_halting() {
+    // The interpreter would loop forever
+    assert!(matches!(
+        vm::run(b"+[]", &[], Some(1000)),
+        Err(Error::StepLimit { limit: 1000 })
+    ));
+}
+
+#[test]
+fn test_input_exhausted() {
+    assert!(matches!(
+        vm::run(b",,", b"a", None),
+        Err(Error::Interpreter(_))
+    ));
+}